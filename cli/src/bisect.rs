@@ -0,0 +1,116 @@
+use crate::boot::{boot_once, BootConfig, Outcome};
+use crate::report::RunReport;
+use crate::session::Farm;
+use crate::soak::iteration_config;
+use colored::Colorize;
+
+/// `git bisect run` exit statuses
+pub const GOOD: u8 = 0;
+pub const BAD: u8 = 1;
+pub const SKIP: u8 = 125;
+/// Anything above 127 stops `git bisect run` altogether
+pub const ABORT: u8 = 128;
+
+/// What to tell `git bisect` when the farm, rather than the commit, is to blame
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OnError {
+    /// Stop bisecting, so that it can be resumed once the farm is fixed
+    #[default]
+    Abort,
+    /// Skip the commit
+    Skip,
+}
+
+impl OnError {
+    pub fn outcome(self) -> Outcome {
+        Outcome::Exited(match self {
+            OnError::Abort => ABORT,
+            OnError::Skip => SKIP,
+        })
+    }
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct BisectArgs {
+    /// Shell command building the image (-i); a failed build skips the commit
+    #[arg(long)]
+    pub build: String,
+
+    /// Boot this many more times before calling the commit bad
+    #[arg(long, default_value_t = 2)]
+    pub retries: u32,
+
+    /// What to tell git bisect when the infrastructure keeps failing
+    #[arg(long, value_enum, default_value_t)]
+    pub on_error: OnError,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Verdict {
+    Good,
+    Bad,
+    /// Nothing can be said about the commit
+    Error,
+}
+
+fn classify(report: &RunReport) -> Verdict {
+    match report.outcome {
+        Outcome::Pass => Verdict::Good,
+        Outcome::Error => Verdict::Error,
+        // The board didn't even get to run the image
+        _ if !report.booted => Verdict::Error,
+        _ if report.failure.as_ref().is_some_and(|f| f.is_error()) => Verdict::Error,
+        _ => Verdict::Bad,
+    }
+}
+
+/// Boot the freshly built image until it passes or runs out of attempts, returning the
+/// reports of all the attempts and what to tell `git bisect`
+pub async fn run(
+    farm: &Farm,
+    config: BootConfig,
+    args: &BisectArgs,
+) -> anyhow::Result<(Vec<RunReport>, Outcome)> {
+    let attempts = args.retries + 1;
+    let mut reports = vec![];
+    let mut verdicts = vec![];
+
+    for i in 1..=attempts {
+        eprintln!("{}", format!("\n=== Attempt {i}/{attempts} ===").bold());
+
+        let mut report = boot_once(farm, iteration_config(&config, i)).await?;
+        report.board = format!("{}#{i}", report.board);
+
+        let verdict = classify(&report);
+        eprintln!("Attempt {i}: {verdict:?}");
+        reports.push(report);
+        verdicts.push(verdict);
+
+        if verdict == Verdict::Good {
+            break;
+        }
+    }
+
+    let outcome = if verdicts.contains(&Verdict::Good) {
+        eprintln!("{}", "\nbisect: good".green());
+        Outcome::Exited(GOOD)
+    } else if verdicts.contains(&Verdict::Bad) {
+        eprintln!("{}", "\nbisect: bad".red());
+        Outcome::Exited(BAD)
+    } else {
+        eprintln!(
+            "{}",
+            format!(
+                "\nbisect: infrastructure errors only, {}",
+                match args.on_error {
+                    OnError::Abort => "aborting",
+                    OnError::Skip => "skipping",
+                }
+            )
+            .yellow()
+        );
+        args.on_error.outcome()
+    };
+
+    Ok((reports, outcome))
+}
@@ -0,0 +1,847 @@
+use crate::criteria::{Criteria, Verdict};
+use crate::exec::{ExecAction, ExecRunner};
+use crate::ktap::KtapParser;
+use crate::lava::LavaParser;
+use crate::report::{Failure, RunReport};
+use crate::results::TestResult;
+use crate::script::{Action, ScriptRunner, ScriptStep};
+use crate::session::{Farm, ServerSink, Session};
+use crate::timeouts::{Timeout, Timers};
+use crate::timing::BootTiming;
+use anyhow::{bail, Context};
+use colored::{Color, Colorize};
+use regex::Regex;
+use sk8brd::console::ConsoleLines;
+use sk8brd::fifo::StatusFifo;
+use sk8brd::log::{ConsoleLog, LogArgs, LogEvent};
+use sk8brd::login::{AutoLogin, LoginArgs};
+use sk8brd::power::{ExitPolicy, PowerArgs, PowerLog, StartPolicy};
+use sk8brd::status::StatusUpdate;
+use sk8brd::{
+    console_print, request_status_update, select_brd, send_ack, send_break, send_console,
+    send_fastboot_continue, send_image, todo, Sk8brdMsgs,
+};
+use std::io::{stderr, stdout, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Keep at most this much of the console around for the reports
+const MAX_TRANSCRIPT: usize = 16 * 1024 * 1024;
+
+/// How the run ended, doubling as the process exit status
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail,
+    Timeout,
+    Error,
+    /// The exit status of a command run on the board
+    Exited(u8),
+}
+
+impl Outcome {
+    /// A pass, or a command on the board that exited successfully
+    pub fn is_success(self) -> bool {
+        matches!(self, Outcome::Pass | Outcome::Exited(0))
+    }
+}
+
+impl From<Outcome> for std::process::ExitCode {
+    fn from(outcome: Outcome) -> Self {
+        Self::from(match outcome {
+            Outcome::Pass => 0,
+            Outcome::Fail => 1,
+            Outcome::Timeout => 2,
+            Outcome::Error => 3,
+            Outcome::Exited(status) => status,
+        })
+    }
+}
+
+/// Everything describing a single boot of a single board
+#[derive(Clone)]
+pub struct BootConfig {
+    pub board: String,
+    /// Prefix for the console and server output, when several boards share the terminal
+    pub label: Option<String>,
+    pub image: Option<Vec<u8>>,
+    pub verbose: bool,
+    /// Don't power on or boot the board, just talk to it
+    pub attach: bool,
+    /// Power the board off before powering it on
+    pub start_power: StartPolicy,
+    /// How long the board stays off when power cycled
+    pub power_off_delay: Duration,
+    pub exit_power: ExitPolicy,
+    /// End the run when fastboot shows up again, rather than sending the image again
+    pub fastboot_once: bool,
+    pub criteria: Criteria,
+    pub timeout: Duration,
+    pub inactivity_timeout: Option<Duration>,
+    pub fastboot_timeout: Option<Duration>,
+    /// Ask for a status update this often once the board is powered on
+    pub status_interval: Option<Duration>,
+    pub milestones: Vec<Regex>,
+    pub timing: bool,
+    pub timing_json: Option<PathBuf>,
+    pub script: Option<Vec<ScriptStep>>,
+    pub exec: Option<ExecRunner>,
+    /// Parse KTAP test results out of the console, they decide the outcome
+    pub ktap: bool,
+    /// Collect LAVA test signals from the console, they decide the outcome
+    pub lava: bool,
+    pub log: LogArgs,
+    pub login: LoginArgs,
+    pub power: PowerArgs,
+}
+
+/// `console.log` -> `console-suffix.log`
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}-{suffix}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{suffix}"),
+    };
+
+    path.with_file_name(name)
+}
+
+impl BootConfig {
+    /// A copy of the config, writing its files (logs, timing report) under suffixed names so
+    /// that several boots can keep theirs apart
+    pub fn with_suffix(&self, suffix: &str) -> Self {
+        let mut config = self.clone();
+        for path in [
+            &mut config.log.log,
+            &mut config.log.log_timestamped,
+            &mut config.timing_json,
+            &mut config.power.power_log,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *path = suffixed_path(path, suffix);
+        }
+
+        config
+    }
+}
+
+/// The state of a single boot, driven by the messages coming from the server
+pub struct Boot {
+    board: String,
+    label: Option<String>,
+    image: Option<Vec<u8>>,
+    verbose: bool,
+    attach: bool,
+    start_power: StartPolicy,
+    power_off_delay: Duration,
+    exit_power: ExitPolicy,
+    /// Waiting for the power off preceding the power on
+    cycling: bool,
+    fastboot_once: bool,
+    criteria: Criteria,
+    timers: Timers,
+    status_interval: Option<Duration>,
+    next_status: Option<tokio::time::Instant>,
+    /// Whether the status updates are printed, rather than only logged
+    print_status: bool,
+    power_log: Option<PowerLog>,
+    status_fifo: Option<StatusFifo>,
+    /// How many of the milestones reached made it to the power log
+    power_milestones: usize,
+    timing: BootTiming,
+    print_timing: bool,
+    timing_json: Option<PathBuf>,
+    script: Option<ScriptRunner>,
+    exec: Option<ExecRunner>,
+    login: Option<AutoLogin>,
+    ktap: Option<KtapParser>,
+    lava: Option<LavaParser>,
+    start: Instant,
+    powered_on: Option<Instant>,
+    /// From power on to the pass or until pattern matching
+    boot_time: Option<Duration>,
+    failure: Option<Failure>,
+    /// The console line that matched a pass or until pattern
+    matched: Option<String>,
+    transcript: Vec<u8>,
+    /// Whether the image was sent (or fastboot told to continue), or the board is attached to
+    booted: bool,
+    log: ConsoleLog,
+    console: ConsoleLines,
+    quit: Arc<Mutex<bool>>,
+}
+
+impl Boot {
+    pub fn new(config: BootConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            board: config.board,
+            label: config.label,
+            image: config.image,
+            verbose: config.verbose,
+            attach: config.attach,
+            start_power: config.start_power,
+            power_off_delay: config.power_off_delay,
+            exit_power: config.exit_power,
+            cycling: false,
+            fastboot_once: config.fastboot_once,
+            criteria: config.criteria,
+            timers: Timers::new(
+                config.timeout,
+                config.inactivity_timeout,
+                config.fastboot_timeout,
+            ),
+            status_interval: config
+                .status_interval
+                .or_else(|| config.power.is_active().then(|| config.power.interval())),
+            next_status: None,
+            print_status: config.status_interval.is_some(),
+            power_log: config
+                .power
+                .power_log
+                .as_deref()
+                .map(PowerLog::new)
+                .transpose()
+                .context("Couldn't create the power log")?,
+            status_fifo: config
+                .power
+                .status_fifo
+                .as_deref()
+                .map(StatusFifo::new)
+                .transpose()
+                .context("Couldn't set up the status FIFO")?,
+            power_milestones: 0,
+            timing: BootTiming::new(config.milestones),
+            print_timing: config.timing,
+            timing_json: config.timing_json,
+            script: config.script.map(ScriptRunner::new),
+            exec: config.exec,
+            login: AutoLogin::new(&config.login),
+            ktap: config.ktap.then(KtapParser::new),
+            lava: config.lava.then(LavaParser::new),
+            start: Instant::now(),
+            powered_on: None,
+            boot_time: None,
+            failure: None,
+            matched: None,
+            transcript: vec![],
+            booted: false,
+            log: ConsoleLog::new(&config.log).context("Couldn't create the console log")?,
+            console: ConsoleLines::new(),
+            quit: Arc::new(Mutex::new(false)),
+        })
+    }
+
+    /// Drive the boot to its end, leaving the board powered as the exit policy says
+    pub async fn run(&mut self, session: &mut Session) -> anyhow::Result<RunReport> {
+        self.start = Instant::now();
+
+        let outcome = match self.run_loop(session).await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                self.status(format!("Error: {e:#}"), Color::Red);
+                self.failure
+                    .get_or_insert_with(|| Failure::Remote(format!("{e:#}")));
+                Outcome::Error
+            }
+        };
+
+        let outcome = self.finish(&mut session.stdin, outcome).await?;
+        Ok(self.report(outcome))
+    }
+
+    /// Wrap up a boot that never got to talk to the server
+    pub fn connection_failed(&mut self, e: anyhow::Error) -> RunReport {
+        self.status(format!("Error: {e:#}"), Color::Red);
+        self.failure = Some(Failure::Remote(format!("{e:#}")));
+        self.report(Outcome::Error)
+    }
+
+    fn report(&mut self, outcome: Outcome) -> RunReport {
+        RunReport {
+            board: self.board.clone(),
+            outcome,
+            failure: self.failure.clone(),
+            duration: self.start.elapsed(),
+            boot_time: self.boot_time,
+            booted: self.booted,
+            matched: self.matched.clone(),
+            milestones: self.timing.milestones().to_vec(),
+            missed_milestones: self.timing.missed(),
+            tests: self
+                .ktap
+                .iter()
+                .flat_map(|k| k.results())
+                .chain(self.lava.iter().flat_map(|l| l.results()))
+                .cloned()
+                .collect(),
+            console: String::from_utf8_lossy(&self.transcript).into_owned(),
+        }
+    }
+
+    /// Say how the run is going, tagged with the board when there are several
+    fn status(&self, msg: String, color: Color) {
+        match &self.label {
+            Some(label) => eprintln!("[{label}] {}", msg.color(color)),
+            None => eprintln!("\n{}", msg.color(color)),
+        }
+    }
+
+    fn verdict(&mut self, verdict: Verdict) -> Outcome {
+        match verdict {
+            Verdict::Pass(line) => {
+                self.status(format!("PASS: `{line}`"), Color::Green);
+                self.boot_time = self.powered_on.map(|t| t.elapsed());
+                self.matched = Some(line);
+                Outcome::Pass
+            }
+            Verdict::Fail(line) => {
+                self.status(format!("FAIL: `{line}`"), Color::Red);
+                self.failure = Some(Failure::FailPattern(line));
+                Outcome::Fail
+            }
+            Verdict::Until(line) => {
+                self.status(format!("Reached `{line}`"), Color::Green);
+                self.boot_time = self.powered_on.map(|t| t.elapsed());
+                self.matched = Some(line);
+                Outcome::Pass
+            }
+        }
+    }
+
+    /// Whether the login, the script or the command is yet to finish
+    fn awaiting(&self) -> bool {
+        self.login.as_ref().is_some_and(|l| !l.is_done())
+            || self.script.as_ref().is_some_and(|s| !s.is_done())
+            || self.exec.as_ref().is_some_and(|e| !e.is_done())
+    }
+
+    fn timed_out(&mut self, timeout: Timeout) -> Outcome {
+        // Running out of time is the expected way out when nothing is to be matched or awaited
+        if matches!(timeout, Timeout::Total(_)) && !self.criteria.expects_pass() && !self.awaiting()
+        {
+            return Outcome::Pass;
+        }
+
+        self.status(format!("Timed out: {timeout}"), Color::Red);
+        let script_error = self
+            .script
+            .as_ref()
+            .and_then(|s| s.unfinished(&format!("timed out ({timeout})")));
+        self.failure = Some(match script_error {
+            Some(e) => {
+                self.status(e.to_string(), Color::Red);
+                Failure::Script(format!("{}: {}", e.step, e.reason))
+            }
+            None => Failure::Timeout(timeout.to_string()),
+        });
+        Outcome::Timeout
+    }
+
+    async fn run_loop(&mut self, session: &mut Session) -> anyhow::Result<Outcome> {
+        let sink = &mut session.stdin;
+
+        select_brd(sink, &self.board).await?;
+        self.timing.mark("select");
+
+        loop {
+            let (deadline, timeout) = self.timers.next_deadline();
+            let script_deadline = self.script.as_ref().and_then(|s| s.deadline());
+            let status_deadline = self.next_status;
+
+            let outcome = tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {
+                    Some(self.timed_out(timeout))
+                }
+
+                _ = tokio::time::sleep_until(script_deadline.unwrap_or(deadline)), if script_deadline.is_some() => {
+                    self.advance(sink).await?
+                }
+
+                _ = tokio::time::sleep_until(status_deadline.unwrap_or(deadline)), if status_deadline.is_some() => {
+                    request_status_update(sink).await?;
+                    self.next_status = self.status_interval.map(|i| tokio::time::Instant::now() + i);
+                    None
+                }
+
+                // Stream of "blue text" - status updates from the server
+                Some(buf) = session.stderr.recv() => {
+                    let s = String::from_utf8_lossy(&buf);
+                    match &self.label {
+                        Some(label) => {
+                            for line in s.lines().filter(|l| !l.trim().is_empty()) {
+                                eprintln!("[{label}] {}", line.trim_end().blue());
+                            }
+                        }
+                        None => eprint!(
+                            "{}\r",
+                            s.split('\n').collect::<Vec<_>>().join("\r\n").blue()
+                        ),
+                    }
+                    stderr().flush()?;
+                    None
+                }
+
+                // Msg handler
+                msg = session.msgs.recv() => {
+                    let Some((msg, msgbuf)) = msg else {
+                        bail!("Connection to the server was lost");
+                    };
+
+                    self.handle_msg(sink, msg.r#type, &msgbuf).await?
+                }
+            };
+
+            self.log_power_milestones()?;
+
+            if let Some(outcome) = outcome {
+                return Ok(outcome);
+            }
+        }
+    }
+
+    /// Tag the power measurements with the milestones reached since the last look
+    fn log_power_milestones(&mut self) -> anyhow::Result<()> {
+        let Some(power_log) = &mut self.power_log else {
+            return Ok(());
+        };
+
+        for milestone in &self.timing.milestones()[self.power_milestones..] {
+            power_log.milestone(&milestone.name)?;
+        }
+        self.power_milestones = self.timing.milestones().len();
+
+        Ok(())
+    }
+
+    async fn handle_msg(
+        &mut self,
+        sink: &mut ServerSink,
+        r#type: u8,
+        msgbuf: &[u8],
+    ) -> anyhow::Result<Option<Outcome>> {
+        match r#type.try_into() {
+            Ok(Sk8brdMsgs::MsgSelectBoard) => {
+                if self.attach || self.start_power == StartPolicy::Leave {
+                    // Whatever runs on the board is already up, poke it for a prompt
+                    self.booted = true;
+                    send_console(sink, b"\n").await?;
+                    return self.advance(sink).await;
+                } else if self.start_power == StartPolicy::Cycle {
+                    self.cycling = true;
+                    send_ack(sink, Sk8brdMsgs::MsgPowerOff).await?
+                } else {
+                    send_ack(sink, Sk8brdMsgs::MsgPowerOn).await?
+                }
+            }
+            Ok(Sk8brdMsgs::MsgPowerOff) => {
+                if self.cycling {
+                    self.cycling = false;
+                    self.log.event(LogEvent::PowerOff)?;
+                    tokio::time::sleep(self.power_off_delay).await;
+                    send_ack(sink, Sk8brdMsgs::MsgPowerOn).await?
+                }
+            }
+            Ok(Sk8brdMsgs::MsgConsole) => return self.handle_console(sink, msgbuf).await,
+            Ok(Sk8brdMsgs::MsgPowerOn) => {
+                // Refresh the timers so that the timeouts actually make sense
+                self.timers.power_on();
+                self.powered_on = Some(Instant::now());
+                self.next_status = self.status_interval.map(|_| tokio::time::Instant::now());
+                self.log.event(LogEvent::PowerOn)?;
+                self.timing.mark("power on");
+            }
+            Ok(Sk8brdMsgs::MsgFastbootPresent) => {
+                if !msgbuf.is_empty() && msgbuf[0] != 0 {
+                    return self.handle_fastboot(sink).await;
+                }
+            }
+            Ok(Sk8brdMsgs::MsgFastbootDownload) => (),
+            Ok(Sk8brdMsgs::MsgStatusUpdate) => self.handle_status(msgbuf)?,
+
+            // Ignore all other valid messages
+            Ok(_) => (),
+            Err(e) => todo!("Received unknown/invalid message: `{e}`"),
+        };
+
+        Ok(None)
+    }
+
+    fn handle_status(&mut self, msgbuf: &[u8]) -> anyhow::Result<()> {
+        if let Some(status_fifo) = &mut self.status_fifo {
+            status_fifo.forward(msgbuf)?;
+        }
+
+        for update in StatusUpdate::parse_all(msgbuf) {
+            let (line, color) = match update {
+                Ok(update) => {
+                    if let Some(power_log) = &mut self.power_log {
+                        power_log.record(&update)?;
+                    }
+                    if !self.print_status {
+                        continue;
+                    }
+                    (update.to_string(), Color::Cyan)
+                }
+                Err(e) => (format!("{e:#}"), Color::Yellow),
+            };
+
+            match &self.label {
+                Some(label) => eprintln!("[{label}] {}", line.color(color)),
+                None => eprint!("{}\r\n", line.color(color)),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_console(
+        &mut self,
+        sink: &mut ServerSink,
+        msgbuf: &[u8],
+    ) -> anyhow::Result<Option<Outcome>> {
+        self.timers.activity();
+        self.log.console(msgbuf)?;
+        if self.transcript.len() < MAX_TRANSCRIPT {
+            self.transcript.extend_from_slice(msgbuf);
+        }
+        self.timing.console();
+        let lines = self.console.push(msgbuf);
+        if self.verbose {
+            match &self.label {
+                // Only whole lines can be told apart from the other boards'
+                Some(label) => {
+                    for line in &lines {
+                        println!("[{label}] {line}");
+                    }
+                }
+                None => console_print(msgbuf).await,
+            }
+        }
+
+        if let Some(ktap) = &mut self.ktap {
+            for line in &lines {
+                ktap.line(line);
+            }
+        }
+        if let Some(lava) = &mut self.lava {
+            for line in &lines {
+                lava.line(line);
+            }
+        }
+
+        // Prompts don't end with a newline, so look at the partial line too
+        let partial = self.console.partial();
+        let lines = lines
+            .iter()
+            .chain((!partial.is_empty()).then_some(&partial));
+        for line in lines.clone() {
+            self.timing.console_line(line);
+        }
+
+        if let Some(verdict) = lines.clone().find_map(|line| self.criteria.check(line)) {
+            return Ok(Some(self.verdict(verdict)));
+        }
+
+        if self.ktap.as_ref().is_some_and(|k| k.is_complete()) {
+            self.status("All planned tests have reported".to_string(), Color::Green);
+            return Ok(Some(Outcome::Pass));
+        }
+
+        if let Some(answer) = self.login.as_mut().and_then(|l| l.console(msgbuf)) {
+            send_console(sink, &answer).await?;
+        }
+
+        if let Some(s) = &mut self.script {
+            s.console(msgbuf);
+        }
+
+        if let Some(e) = &mut self.exec {
+            let action = e.console(msgbuf);
+            if let Some(status) = handle_exec(action, sink, self.label.as_deref()).await? {
+                let status = status.clamp(0, 255) as u8;
+                if status != 0 {
+                    self.failure = Some(Failure::Command(status));
+                }
+                return Ok(Some(Outcome::Exited(status)));
+            }
+        }
+
+        self.advance(sink).await
+    }
+
+    async fn handle_fastboot(&mut self, sink: &mut ServerSink) -> anyhow::Result<Option<Outcome>> {
+        if self.fastboot_once && self.booted {
+            self.status("Back in fastboot".to_string(), Color::Green);
+            return Ok(Some(Outcome::Pass));
+        }
+
+        self.timers.fastboot_present();
+        self.log.event(LogEvent::FastbootPresent)?;
+        self.timing.mark("fastboot present");
+
+        match &self.image {
+            Some(image) => {
+                self.timing.mark("upload start");
+                if let Err(e) = send_image(sink, image, &self.quit).await {
+                    self.failure = Some(Failure::Upload(format!("{e:#}")));
+                    return Err(e.context("Couldn't send the image"));
+                }
+                self.log.event(LogEvent::ImageSent)?;
+                self.timing.mark("upload end");
+            }
+            None => {
+                send_fastboot_continue(sink).await?;
+                self.log.event(LogEvent::FastbootContinue)?;
+                self.timing.mark("fastboot continue");
+            }
+        }
+
+        // The upload may take a while, don't count it as silence
+        self.timers.activity();
+        self.timing.boot_started();
+        self.booted = true;
+
+        self.advance(sink).await
+    }
+
+    /// Move the post-boot steps along: the login first, then the script, then the command to run
+    async fn advance(&mut self, sink: &mut ServerSink) -> anyhow::Result<Option<Outcome>> {
+        if !self.booted || self.login.as_ref().is_some_and(|l| !l.is_done()) {
+            return Ok(None);
+        }
+
+        if let Some(s) = &mut self.script {
+            s.start();
+            let actions = match s.advance() {
+                Ok(actions) => actions,
+                Err(e) => {
+                    self.status(e.to_string(), Color::Red);
+                    self.failure = Some(Failure::Script(format!("{}: {}", e.step, e.reason)));
+                    return Ok(Some(Outcome::Fail));
+                }
+            };
+
+            for action in actions {
+                match action {
+                    Action::Send(buf) => send_console(sink, &buf).await?,
+                    Action::Break => {
+                        send_break(sink).await?;
+                        self.log.event(LogEvent::BreakSent)?;
+                    }
+                }
+            }
+
+            if !s.is_done() {
+                return Ok(None);
+            }
+
+            // A finished script is as good as a pass, unless something else is still awaited
+            if self.exec.is_none() && !self.criteria.expects_pass() {
+                self.status("Script completed".to_string(), Color::Green);
+                return Ok(Some(Outcome::Pass));
+            }
+        }
+
+        if let Some(e) = &mut self.exec {
+            e.start();
+        }
+
+        Ok(None)
+    }
+
+    async fn finish(&mut self, sink: &mut ServerSink, outcome: Outcome) -> anyhow::Result<Outcome> {
+        if self.print_timing {
+            self.timing.print_table();
+        }
+        if let Some(path) = &self.timing_json {
+            self.timing
+                .write_json(path)
+                .with_context(|| format!("Couldn't write {}", path.display()))?;
+        }
+
+        // A run that went fine otherwise is only as good as its test results
+        let mut outcome = outcome;
+        if let Some(ktap) = &mut self.ktap {
+            ktap.finish();
+            ktap.print_summary();
+            if outcome == Outcome::Pass && !ktap.passed() {
+                let failed = ktap
+                    .results()
+                    .iter()
+                    .filter(|t| t.result == TestResult::Fail)
+                    .count();
+                self.failure = Some(Failure::Tests(format!(
+                    "{failed} tests failed, {} errors",
+                    ktap.errors().len()
+                )));
+                outcome = Outcome::Fail;
+            }
+        }
+        if let Some(lava) = &mut self.lava {
+            lava.finish();
+            lava.print_summary();
+            if outcome == Outcome::Pass && !lava.passed() {
+                let failed = lava
+                    .results()
+                    .iter()
+                    .filter(|t| t.result == TestResult::Fail)
+                    .count();
+                self.failure = Some(Failure::Tests(format!(
+                    "{failed} LAVA test cases failed, {} errors",
+                    lava.errors().len()
+                )));
+                outcome = Outcome::Fail;
+            }
+        }
+
+        // Power off the board on goodbye as the exit policy says, unless it was only borrowed.
+        // Don't insist if the connection is what failed in the first place.
+        let failed = !outcome.is_success();
+        if !self.attach && self.exit_power.powers_off(failed) {
+            match send_ack(sink, Sk8brdMsgs::MsgPowerOff).await {
+                Ok(()) => self.log.event(LogEvent::PowerOff)?,
+                Err(_) if outcome == Outcome::Error => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(outcome)
+    }
+}
+
+/// Connect to the farm and boot the board once
+pub async fn boot_once(farm: &Farm, config: BootConfig) -> anyhow::Result<RunReport> {
+    let mut boot = Boot::new(config)?;
+
+    Ok(match Session::connect(farm).await {
+        Ok(mut session) => boot.run(&mut session).await?,
+        Err(e) => boot.connection_failed(e),
+    })
+}
+
+/// The report of a board whose run broke off with an error, so that the other boards of a run
+/// can carry on
+pub fn run_failed(label: &str, start: Instant, e: anyhow::Error) -> RunReport {
+    eprintln!("[{label}] {}", format!("Error: {e:#}").red());
+
+    RunReport {
+        board: label.to_string(),
+        outcome: Outcome::Error,
+        failure: Some(Failure::Remote(format!("{e:#}"))),
+        duration: start.elapsed(),
+        boot_time: None,
+        booted: false,
+        matched: None,
+        milestones: vec![],
+        missed_milestones: vec![],
+        tests: vec![],
+        console: String::new(),
+    }
+}
+
+/// Carry out what the command runner asks for, returning the command's exit status once it has
+/// finished
+async fn handle_exec(
+    action: Option<ExecAction>,
+    sink: &mut ServerSink,
+    label: Option<&str>,
+) -> anyhow::Result<Option<i32>> {
+    match action {
+        Some(ExecAction::Send(buf)) => send_console(sink, &buf).await?,
+        Some(ExecAction::Finished { output, status }) => {
+            match label {
+                Some(label) => output.lines().for_each(|l| println!("[{label}] {l}")),
+                None => print!("{output}"),
+            }
+            stdout().flush()?;
+            return Ok(Some(status));
+        }
+        None => (),
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exec::ExecArgs;
+
+    fn config() -> BootConfig {
+        BootConfig {
+            board: "db845c".to_string(),
+            label: None,
+            image: None,
+            verbose: false,
+            attach: false,
+            start_power: StartPolicy::default(),
+            power_off_delay: Duration::ZERO,
+            exit_power: ExitPolicy::default(),
+            fastboot_once: false,
+            criteria: Criteria::default(),
+            timeout: Duration::from_secs(60),
+            inactivity_timeout: None,
+            fastboot_timeout: None,
+            status_interval: None,
+            milestones: vec![],
+            timing: false,
+            timing_json: None,
+            script: None,
+            exec: None,
+            ktap: false,
+            lava: false,
+            log: LogArgs::default(),
+            login: LoginArgs::default(),
+            power: PowerArgs::default(),
+        }
+    }
+
+    const TOTAL: Timeout = Timeout::Total(Duration::from_secs(60));
+
+    #[test]
+    fn total_timeout_without_criteria_passes() {
+        let mut boot = Boot::new(config()).unwrap();
+        assert_eq!(boot.timed_out(TOTAL), Outcome::Pass);
+        assert!(boot.failure.is_none());
+    }
+
+    #[test]
+    fn total_timeout_fails_an_unfinished_script() {
+        let mut config = config();
+        config.script = Some(crate::script::parse("sendline root\nexpect # $\n").unwrap());
+        let mut boot = Boot::new(config).unwrap();
+        let script = boot.script.as_mut().unwrap();
+        script.start();
+        script.advance().unwrap();
+        script.console(b"root\r\nPassword: ");
+
+        assert_eq!(boot.timed_out(TOTAL), Outcome::Timeout);
+        let failure = boot.failure.unwrap();
+        assert_eq!(failure.kind(), "script");
+        assert!(
+            failure
+                .message()
+                .starts_with("line 2: `expect # $`: timed out"),
+            "{}",
+            failure.message()
+        );
+    }
+
+    #[test]
+    fn total_timeout_fails_an_unfinished_command() {
+        let mut config = config();
+        config.exec = Some(ExecRunner::new(&ExecArgs {
+            prompt: Regex::new(r"[#$] $").unwrap(),
+            attach: false,
+            cmd: vec!["false".to_string()],
+        }));
+        let mut boot = Boot::new(config).unwrap();
+
+        assert_eq!(boot.timed_out(TOTAL), Outcome::Timeout);
+        assert_eq!(boot.failure.unwrap().kind(), "timeout");
+    }
+}
@@ -0,0 +1,171 @@
+use clap::Parser;
+use std::ffi::OsString;
+use std::path::Path;
+
+/// Switches sk8brd-cli over to the cdba command line when given first
+pub const COMPAT_FLAG: &str = "--cdba-compat";
+
+/// The C cdba client's command line, for scripts that call `cdba`
+#[derive(Parser, Debug)]
+#[command(name = "cdba", disable_help_flag = true)]
+struct CdbaArgs {
+    /// Host running cdba-server
+    #[arg(short = 'h')]
+    host: String,
+
+    /// Board to boot
+    #[arg(short)]
+    board: Option<String>,
+
+    /// How many times to power cycle the board and boot it again once a boot ends
+    #[arg(short = 'c', default_value_t = 0)]
+    power_cycles: u32,
+
+    /// Total timeout in seconds
+    #[arg(short = 't', default_value_t = 600)]
+    timeout: u64,
+
+    /// Inactivity timeout in seconds
+    #[arg(short = 'T')]
+    inactivity_timeout: Option<u64>,
+
+    /// Send the image again whenever fastboot shows up, rather than ending the run
+    #[arg(short = 'R', default_value_t = false)]
+    fastboot_repeat: bool,
+
+    /// Named pipe to write the status updates to
+    #[arg(short = 'S')]
+    status_fifo: Option<OsString>,
+
+    /// Show information about the board
+    #[arg(short = 'i', default_value_t = false)]
+    info: bool,
+
+    /// List the available boards
+    #[arg(short = 'l', default_value_t = false)]
+    list: bool,
+
+    #[arg(long, action = clap::ArgAction::Help)]
+    help: Option<bool>,
+
+    /// Boot image to send
+    image: Option<OsString>,
+}
+
+/// Whether to speak cdba: run as (a symlink called) `cdba`, or given `--cdba-compat`
+pub fn is_compat(argv: &[OsString]) -> bool {
+    let called_cdba = argv
+        .first()
+        .and_then(|arg0| Path::new(arg0).file_name())
+        .is_some_and(|name| name == "cdba");
+
+    called_cdba || argv.get(1).is_some_and(|arg| arg == COMPAT_FLAG)
+}
+
+/// Turn a cdba command line into the equivalent sk8brd-cli one
+pub fn translate(argv: Vec<OsString>) -> Vec<OsString> {
+    let mut argv = argv.into_iter();
+    let arg0 = argv.next().unwrap_or_else(|| "cdba".into());
+    let rest: Vec<_> = argv.filter(|arg| arg != COMPAT_FLAG).collect();
+    let cdba = CdbaArgs::parse_from(std::iter::once(OsString::from("cdba")).chain(rest));
+
+    // cdba always shows the console
+    let mut args: Vec<OsString> = vec![arg0, "-v".into(), "-f".into(), cdba.host.into()];
+    let mut push = |flag: &str, value: OsString| {
+        args.push(flag.into());
+        args.push(value);
+    };
+
+    if let Some(board) = cdba.board {
+        push("-b", board.into());
+    }
+    push("-t", cdba.timeout.to_string().into());
+    if let Some(t) = cdba.inactivity_timeout {
+        push("--inactivity-timeout", t.to_string().into());
+    }
+    if cdba.power_cycles > 0 {
+        // Every boot after the first starts with a power cycle
+        push("--repeat", (cdba.power_cycles + 1).to_string().into());
+    }
+    if let Some(fifo) = cdba.status_fifo {
+        push("--status-fifo", fifo);
+    }
+    if let Some(image) = cdba.image {
+        push("-i", image);
+    }
+    if !cdba.fastboot_repeat {
+        args.push("--fastboot-once".into());
+    }
+
+    if cdba.list {
+        args.push("list".into());
+    } else if cdba.info {
+        args.push("info".into());
+    }
+
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &str) -> Vec<OsString> {
+        args.split_whitespace().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn translate_cdba_flags() {
+        let cases = [
+            (
+                "cdba -h farm -b db845c boot.img",
+                "cdba -v -f farm -b db845c -t 600 -i boot.img --fastboot-once",
+            ),
+            (
+                "cdba -h farm -b db845c -c 2 boot.img",
+                "cdba -v -f farm -b db845c -t 600 --repeat 3 -i boot.img --fastboot-once",
+            ),
+            (
+                "cdba -h farm -b db845c -c 0 boot.img",
+                "cdba -v -f farm -b db845c -t 600 -i boot.img --fastboot-once",
+            ),
+            (
+                "cdba -h farm -b db845c -R -t 30 -T 10 boot.img",
+                "cdba -v -f farm -b db845c -t 30 --inactivity-timeout 10 -i boot.img",
+            ),
+            (
+                "cdba -h farm -b db845c -S /tmp/status boot.img",
+                "cdba -v -f farm -b db845c -t 600 --status-fifo /tmp/status -i boot.img \
+                 --fastboot-once",
+            ),
+            (
+                "cdba -h farm -l",
+                "cdba -v -f farm -t 600 --fastboot-once list",
+            ),
+            (
+                "cdba -h farm -b db845c -i",
+                "cdba -v -f farm -b db845c -t 600 --fastboot-once info",
+            ),
+            (
+                "sk8brd-cli --cdba-compat -h farm -b db845c boot.img",
+                "sk8brd-cli -v -f farm -b db845c -t 600 -i boot.img --fastboot-once",
+            ),
+        ];
+
+        for (cdba, expected) in cases {
+            let translated = translate(argv(cdba));
+            assert_eq!(translated, argv(expected), "{cdba}");
+            // ...and sk8brd-cli takes it
+            crate::Args::try_parse_from(translated).unwrap();
+        }
+    }
+
+    #[test]
+    fn compat_detection() {
+        assert!(is_compat(&argv("cdba -h farm")));
+        assert!(is_compat(&argv("/usr/local/bin/cdba -h farm")));
+        assert!(is_compat(&argv("sk8brd-cli --cdba-compat -h farm")));
+        assert!(!is_compat(&argv("sk8brd-cli -f farm")));
+        assert!(!is_compat(&argv("sk8brd-cli -f farm --cdba-compat")));
+    }
+}
@@ -0,0 +1,151 @@
+use crate::boot::Outcome;
+use crate::session::{Farm, Session};
+use anyhow::bail;
+use colored::Colorize;
+use sk8brd::{console_print, send_ack, send_break, Sk8brdMsgs};
+use std::future::Future;
+use std::io::{stderr, Write};
+use std::time::Duration;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum PowerAction {
+    On,
+    Off,
+    /// Power off, then on again
+    Cycle,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Switch {
+    On,
+    Off,
+}
+
+/// These operations act on exactly one board
+fn single_board(boards: &[String]) -> anyhow::Result<&str> {
+    match boards {
+        [board] => Ok(board),
+        _ => bail!("This acts on a single board (-b <BOARD>)"),
+    }
+}
+
+/// Run an operation, giving up once the timeout hits
+pub async fn with_timeout(
+    timeout: Duration,
+    op: impl Future<Output = anyhow::Result<()>>,
+) -> anyhow::Result<Outcome> {
+    match tokio::time::timeout(timeout, op).await {
+        Ok(result) => result.map(|()| Outcome::Pass),
+        Err(_) => {
+            eprintln!(
+                "{}",
+                format!("Timed out after {} s", timeout.as_secs()).red()
+            );
+            Ok(Outcome::Timeout)
+        }
+    }
+}
+
+/// Connect and select the board
+async fn open(farm: &Farm, board: &str) -> anyhow::Result<Session> {
+    let mut session = Session::connect(farm).await?;
+    session.select_board(board).await?;
+    Ok(session)
+}
+
+pub async fn power(
+    farm: &Farm,
+    boards: &[String],
+    action: PowerAction,
+    off_delay: Duration,
+    timeout: Duration,
+) -> anyhow::Result<Outcome> {
+    let board = single_board(boards)?;
+
+    with_timeout(timeout, async {
+        let mut session = open(farm, board).await?;
+        if matches!(action, PowerAction::Off | PowerAction::Cycle) {
+            session.request(Sk8brdMsgs::MsgPowerOff).await?;
+            eprintln!("{board}: powered off");
+        }
+        if action == PowerAction::Cycle {
+            tokio::time::sleep(off_delay).await;
+        }
+        if matches!(action, PowerAction::On | PowerAction::Cycle) {
+            session.request(Sk8brdMsgs::MsgPowerOn).await?;
+            eprintln!("{board}: powered on");
+        }
+        session.disconnect().await
+    })
+    .await
+}
+
+pub async fn vbus(
+    farm: &Farm,
+    boards: &[String],
+    state: Switch,
+    timeout: Duration,
+) -> anyhow::Result<Outcome> {
+    let board = single_board(boards)?;
+
+    with_timeout(timeout, async {
+        let mut session = Session::connect(farm).await?;
+        if !session.board_info(board).await?.supports_vbus() {
+            bail!("{board} has no VBUS control");
+        }
+
+        session.select_board(board).await?;
+        let r#type = match state {
+            Switch::On => Sk8brdMsgs::MsgVbusOn,
+            Switch::Off => Sk8brdMsgs::MsgVbusOff,
+        };
+        send_ack(&mut session.stdin, r#type).await?;
+        session.disconnect().await
+    })
+    .await
+}
+
+pub async fn console_break(
+    farm: &Farm,
+    boards: &[String],
+    timeout: Duration,
+) -> anyhow::Result<Outcome> {
+    let board = single_board(boards)?;
+
+    with_timeout(timeout, async {
+        let mut session = open(farm, board).await?;
+        send_break(&mut session.stdin).await?;
+        session.disconnect().await
+    })
+    .await
+}
+
+/// Print the board's console, without touching its power, until interrupted or the server
+/// hangs up
+pub async fn console(farm: &Farm, boards: &[String]) -> anyhow::Result<Outcome> {
+    let board = single_board(boards)?;
+    let mut session = open(farm, board).await?;
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+
+            Some(buf) = session.stderr.recv() => {
+                eprint!("{}", String::from_utf8_lossy(&buf).blue());
+                stderr().flush()?;
+            }
+
+            msg = session.msgs.recv() => {
+                let Some((msg, buf)) = msg else {
+                    break;
+                };
+
+                if matches!(msg.r#type.try_into(), Ok(Sk8brdMsgs::MsgConsole)) {
+                    console_print(&buf).await;
+                }
+            }
+        }
+    }
+
+    Ok(Outcome::Pass)
+}
@@ -0,0 +1,40 @@
+use regex::Regex;
+
+/// A console pattern that ended the run
+#[derive(Debug)]
+pub enum Verdict {
+    Pass(String),
+    Fail(String),
+    Until(String),
+}
+
+/// Pass/fail/until patterns matched against console lines
+#[derive(Clone, Debug, Default)]
+pub struct Criteria {
+    pub pass: Vec<Regex>,
+    pub fail: Vec<Regex>,
+    pub until: Option<Regex>,
+}
+
+impl Criteria {
+    /// Whether the run is expected to prove itself by matching a pass pattern
+    pub fn expects_pass(&self) -> bool {
+        !self.pass.is_empty()
+    }
+
+    /// Check a single console line, fail patterns taking precedence
+    pub fn check(&self, line: &str) -> Option<Verdict> {
+        if self.fail.iter().any(|re| re.is_match(line)) {
+            return Some(Verdict::Fail(line.to_string()));
+        }
+
+        if self.pass.iter().any(|re| re.is_match(line)) {
+            return Some(Verdict::Pass(line.to_string()));
+        }
+
+        match &self.until {
+            Some(re) if re.is_match(line) => Some(Verdict::Until(line.to_string())),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crate::boot::Outcome;
+use regex::Regex;
+use sk8brd::console::ConsoleTail;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// While waiting for the prompt, only the tail of the output is of any interest
+const PROMPT_SEARCH_WINDOW: usize = 4096;
+
+/// What sk8brd-cli exits with when it couldn't get the command's exit status, like timeout(1)
+/// and env(1) do
+pub const FAILURE: u8 = 125;
+
+/// The command's exit status is sk8brd-cli's own, so any other failure (a fail pattern, a
+/// timeout, an error) is moved out of its way
+pub fn outcome(outcome: Outcome) -> Outcome {
+    match outcome {
+        Outcome::Pass | Outcome::Exited(_) => outcome,
+        Outcome::Fail | Outcome::Timeout | Outcome::Error => Outcome::Exited(FAILURE),
+    }
+}
+
+/// Quote an argument for the board's shell, unless it's made of harmless characters only
+fn shell_quote(arg: &str) -> String {
+    let harmless = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(harmless) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ExecArgs {
+    /// Regex matching the shell prompt
+    #[arg(long, default_value = r"[#$] $", value_parser = Regex::new)]
+    pub prompt: Regex,
+
+    /// Don't power on or boot the board, talk to whatever is already running
+    #[arg(long, default_value_t = false)]
+    pub attach: bool,
+
+    /// The command to run, each argument quoted for the board's shell (use `sh -c '...'` for
+    /// pipes and the like)
+    #[arg(last = true, required = true)]
+    pub cmd: Vec<String>,
+}
+
+#[derive(Clone)]
+enum State {
+    /// Waiting for the shell to come up
+    Prompt,
+    /// The command was sent, waiting for its output to be framed by the markers
+    Running,
+    Done,
+}
+
+/// What the caller has to do on behalf of the command runner
+pub enum ExecAction {
+    Send(Vec<u8>),
+    Finished { output: String, status: i32 },
+}
+
+/// Runs a single shell command over the console, capturing its output and exit status.
+///
+/// The command is wrapped in begin/end markers, printed in two pieces so that the terminal
+/// echoing the command line back doesn't look like the markers themselves.
+#[derive(Clone)]
+pub struct ExecRunner {
+    cmd: String,
+    prompt: Regex,
+    tag: String,
+    begin: String,
+    end: Regex,
+    started: bool,
+    state: State,
+    /// Console output not consumed yet
+    pending: ConsoleTail,
+}
+
+impl ExecRunner {
+    pub fn new(args: &ExecArgs) -> Self {
+        let nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64
+            ^ process::id() as u64;
+        let tag = format!("sk8brd_{nonce:x}");
+
+        Self {
+            cmd: args
+                .cmd
+                .iter()
+                .map(|arg| shell_quote(arg))
+                .collect::<Vec<_>>()
+                .join(" "),
+            prompt: args.prompt.clone(),
+            begin: format!("__{tag}_begin\n"),
+            end: Regex::new(&format!(r"\n__{tag}_end (\d+)\n")).unwrap(),
+            tag,
+            started: false,
+            state: State::Prompt,
+            pending: ConsoleTail::new(PROMPT_SEARCH_WINDOW),
+        }
+    }
+
+    /// Start looking for a prompt
+    pub fn start(&mut self) {
+        self.started = true;
+    }
+
+    /// Whether the command has finished
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    fn command_line(&self) -> Vec<u8> {
+        let tag = &self.tag;
+        format!(
+            "printf '%s%s\\n' __ '{tag}_begin'; {}; printf '\\n%s%s %d\\n' __ '{tag}_end' $?\n",
+            self.cmd
+        )
+        .into_bytes()
+    }
+
+    /// Feed console output, returning what to do next
+    pub fn console(&mut self, buf: &[u8]) -> Option<ExecAction> {
+        if !self.started {
+            return None;
+        }
+
+        self.pending.push(buf);
+
+        match self.state {
+            State::Prompt => {
+                let Some(m) = self.prompt.find(self.pending.as_str()) else {
+                    self.pending.trim();
+                    return None;
+                };
+                self.pending.consume(m.end());
+                self.state = State::Running;
+                Some(ExecAction::Send(self.command_line()))
+            }
+            State::Running => {
+                let pending = self.pending.as_str();
+                let start = pending.find(&self.begin)? + self.begin.len();
+                let caps = self.end.captures(&pending[start..])?;
+                let end = caps.get(0).unwrap().start();
+
+                // The end marker is preceded by a newline of our own, the rest is the output
+                let output = pending[start..start + end].to_string();
+                let status = caps[1].parse().unwrap_or(255);
+                self.state = State::Done;
+                Some(ExecAction::Finished { output, status })
+            }
+            State::Done => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoting() {
+        assert_eq!(shell_quote("ls"), "ls");
+        assert_eq!(shell_quote("/sys/class/net"), "/sys/class/net");
+        assert_eq!(shell_quote("a b;c"), "'a b;c'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote("$HOME"), "'$HOME'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn outcomes() {
+        assert_eq!(outcome(Outcome::Exited(0)), Outcome::Exited(0));
+        assert_eq!(outcome(Outcome::Exited(2)), Outcome::Exited(2));
+        assert_eq!(outcome(Outcome::Exited(255)), Outcome::Exited(255));
+        assert_eq!(outcome(Outcome::Fail), Outcome::Exited(FAILURE));
+        assert_eq!(outcome(Outcome::Timeout), Outcome::Exited(FAILURE));
+        assert_eq!(outcome(Outcome::Error), Outcome::Exited(FAILURE));
+    }
+}
@@ -0,0 +1,450 @@
+use crate::boot::BootConfig;
+use crate::criteria::Criteria;
+use crate::script::{self, ScriptStep};
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use regex::Regex;
+use serde::Deserialize;
+use sk8brd::log::{LogArgs, LogTimestamps};
+use sk8brd::login::{Credentials, LoginArgs};
+use sk8brd::power::{ExitPolicy, PowerArgs, StartPolicy, DEFAULT_POWER_INTERVAL};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// A job file, describing a whole run of sk8brd-cli
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    /// A host, or the name of a farm in the config file
+    pub farm: String,
+    /// Like the flags, these beat the config file's farm settings, which beat the defaults
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    pub board: String,
+    /// Boot image to send; without one (or a build), fastboot is told to continue
+    pub image: Option<PathBuf>,
+    pub build: Option<Build>,
+    /// Power the board off before powering it on, like `power.start: cycle`
+    #[serde(default)]
+    pub power_cycle: bool,
+    #[serde(default)]
+    pub power: Power,
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    /// Seconds between status updates (voltage, current, ...) once powered on
+    pub status_interval: Option<u64>,
+    /// Seconds between the status updates recorded to `artifacts.power_log`
+    pub power_interval: Option<f64>,
+    pub login: Option<Login>,
+    /// Written as `- sendline: root`, `- break`, ...
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+    pub steps: Vec<Step>,
+    #[serde(default)]
+    pub pass: Vec<String>,
+    #[serde(default)]
+    pub fail: Vec<String>,
+    pub until: Option<String>,
+    #[serde(default)]
+    pub milestones: Vec<String>,
+    #[serde(default)]
+    pub ktap: bool,
+    #[serde(default)]
+    pub lava: bool,
+    #[serde(default)]
+    pub artifacts: Artifacts,
+}
+
+/// How to produce the boot image before booting it
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Build {
+    /// Shell command building the image
+    pub command: String,
+    /// Where the command leaves the image
+    pub image: PathBuf,
+    /// Directory to run the command in
+    pub dir: Option<PathBuf>,
+}
+
+/// All in seconds
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Timeouts {
+    #[serde(default = "default_total_timeout")]
+    pub total: u64,
+    pub inactivity: Option<u64>,
+    pub fastboot: Option<u64>,
+}
+
+fn default_total_timeout() -> u64 {
+    60
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            total: default_total_timeout(),
+            inactivity: None,
+            fastboot: None,
+        }
+    }
+}
+
+/// Power policies, see --start-power, --power-off-delay and --exit-power
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Power {
+    pub start: Option<String>,
+    pub off_delay: Option<f64>,
+    pub exit: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Login {
+    pub user: String,
+    pub password: Option<String>,
+    pub login_prompt: Option<String>,
+    pub password_prompt: Option<String>,
+}
+
+/// A script step, with the same meaning as in `--script` files
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum Step {
+    Send(String),
+    Sendline(String),
+    Expect(String),
+    Timeout(f64),
+    Sleep(f64),
+    Break,
+}
+
+impl Step {
+    /// The step as a script line, to tell which one went wrong
+    fn text(&self) -> String {
+        match self {
+            Step::Send(s) => format!("send {s}"),
+            Step::Sendline(s) => format!("sendline {s}"),
+            Step::Expect(s) => format!("expect {s}"),
+            Step::Timeout(secs) => format!("timeout {secs}"),
+            Step::Sleep(secs) => format!("sleep {secs}"),
+            Step::Break => "break".to_string(),
+        }
+    }
+
+    /// The arguments are taken as they are, no trimming or unquoting like in script lines
+    fn script_step(&self) -> anyhow::Result<script::Step> {
+        Ok(match self {
+            Step::Send(s) => script::Step::Send(script::unescape(s)?),
+            Step::Sendline(s) => {
+                script::Step::Send([script::unescape(s)?, b"\n".to_vec()].concat())
+            }
+            Step::Expect(s) => script::Step::Expect(Regex::new(s)?),
+            Step::Timeout(secs) => script::Step::Timeout(script::secs(*secs)?),
+            Step::Sleep(secs) => script::Step::Sleep(script::secs(*secs)?),
+            Step::Break => script::Step::Break,
+        })
+    }
+}
+
+/// Files to write the results of the run to
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Artifacts {
+    pub junit: Option<PathBuf>,
+    pub tap: Option<PathBuf>,
+    pub timing: Option<PathBuf>,
+    pub log: Option<PathBuf>,
+    pub log_timestamped: Option<PathBuf>,
+    pub log_timestamps: Option<String>,
+    pub log_max_size: Option<u64>,
+    /// CSV of the power measurements, see --power-log
+    pub power_log: Option<PathBuf>,
+}
+
+fn regexes(what: &str, patterns: &[String], errors: &mut Vec<String>) -> Vec<Regex> {
+    patterns
+        .iter()
+        .filter_map(|p| {
+            Regex::new(p)
+                .map_err(|e| errors.push(format!("{what}: invalid pattern `{p}`: {e}")))
+                .ok()
+        })
+        .collect()
+}
+
+/// A job that passed validation, ready to be booted
+pub struct ValidJob {
+    pub job: Job,
+    pub criteria: Criteria,
+    pub milestones: Vec<Regex>,
+    pub script: Vec<ScriptStep>,
+    pub login: LoginArgs,
+    pub log: LogArgs,
+    pub start_power: StartPolicy,
+    pub exit_power: ExitPolicy,
+}
+
+impl Job {
+    /// Check everything that can be checked without touching the farm, reporting all
+    /// problems at once
+    pub fn validate(self) -> anyhow::Result<ValidJob> {
+        let mut errors = vec![];
+
+        if self.farm.is_empty() {
+            errors.push("farm: must not be empty".to_string());
+        }
+        if self.board.is_empty() {
+            errors.push("board: must not be empty".to_string());
+        }
+        if self.image.is_some() && self.build.is_some() {
+            errors.push("image and build are mutually exclusive".to_string());
+        }
+        if let Some(image) = &self.image {
+            if !image.is_file() {
+                errors.push(format!("image: {} not found", image.display()));
+            }
+        }
+        if self.timeouts.total == 0 {
+            errors.push("timeouts.total: must be positive".to_string());
+        }
+        if self.status_interval == Some(0) {
+            errors.push("status_interval: must be positive".to_string());
+        }
+        if self
+            .power_interval
+            .is_some_and(|i| !i.is_finite() || i <= 0.0)
+        {
+            errors.push("power_interval: must be positive".to_string());
+        }
+        if self
+            .power
+            .off_delay
+            .is_some_and(|d| !d.is_finite() || d < 0.0)
+        {
+            errors.push("power.off_delay: must be a non-negative number of seconds".to_string());
+        }
+
+        let criteria = Criteria {
+            pass: regexes("pass", &self.pass, &mut errors),
+            fail: regexes("fail", &self.fail, &mut errors),
+            until: regexes("until", self.until.as_slice(), &mut errors).pop(),
+        };
+        let milestones = regexes("milestones", &self.milestones, &mut errors);
+
+        let script = self
+            .steps
+            .iter()
+            .enumerate()
+            .filter_map(|(i, step)| match step.script_step() {
+                Ok(parsed) => Some(ScriptStep {
+                    line: i + 1,
+                    text: step.text(),
+                    step: parsed,
+                }),
+                Err(e) => {
+                    errors.push(format!("steps[{i}]: {e:#}"));
+                    None
+                }
+            })
+            .collect();
+
+        let mut login = LoginArgs::default();
+        if let Some(l) = &self.login {
+            login.login = Some(Credentials {
+                user: l.user.clone(),
+                password: l.password.clone(),
+            });
+            login.login_prompt =
+                regexes("login.login_prompt", l.login_prompt.as_slice(), &mut errors).pop();
+            login.password_prompt = regexes(
+                "login.password_prompt",
+                l.password_prompt.as_slice(),
+                &mut errors,
+            )
+            .pop();
+        }
+
+        let a = &self.artifacts;
+        let log_timestamps = match &a.log_timestamps {
+            Some(s) => LogTimestamps::from_str(s, true).unwrap_or_else(|_| {
+                errors.push(format!(
+                    "artifacts.log_timestamps: `{s}` is not one of wall, power-on, image-sent"
+                ));
+                LogTimestamps::default()
+            }),
+            None => LogTimestamps::default(),
+        };
+        let log = LogArgs {
+            log: a.log.clone(),
+            log_timestamped: a.log_timestamped.clone(),
+            log_timestamps,
+            log_max_size: a.log_max_size,
+        };
+
+        let start_power = match &self.power.start {
+            Some(s) => StartPolicy::from_str(s, true).unwrap_or_else(|_| {
+                errors.push(format!("power.start: `{s}` is not one of on, cycle, leave"));
+                StartPolicy::default()
+            }),
+            None if self.power_cycle => StartPolicy::Cycle,
+            None => StartPolicy::default(),
+        };
+        let exit_power = match &self.power.exit {
+            Some(s) => ExitPolicy::from_str(s, true).unwrap_or_else(|_| {
+                errors.push(format!(
+                    "power.exit: `{s}` is not one of off, leave, off-on-failure"
+                ));
+                ExitPolicy::default()
+            }),
+            None => ExitPolicy::default(),
+        };
+
+        if !errors.is_empty() {
+            bail!(errors.join("\n"));
+        }
+
+        Ok(ValidJob {
+            job: self,
+            criteria,
+            milestones,
+            script,
+            login,
+            log,
+            start_power,
+            exit_power,
+        })
+    }
+}
+
+pub fn load(path: &Path) -> anyhow::Result<ValidJob> {
+    let yaml =
+        fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    let job: Job =
+        serde_yaml::from_str(&yaml).with_context(|| format!("Invalid job {}", path.display()))?;
+    job.validate()
+        .with_context(|| format!("Invalid job {}", path.display()))
+}
+
+/// Run a build command through the shell, failing unless it succeeds
+pub fn build(command: &str, dir: Option<&Path>) -> anyhow::Result<()> {
+    eprintln!("Building: {command}");
+
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+
+    let status = cmd
+        .status()
+        .with_context(|| format!("Couldn't run `{command}`"))?;
+    if !status.success() {
+        bail!("`{command}` failed: {status}");
+    }
+
+    Ok(())
+}
+
+impl ValidJob {
+    /// Build the image if need be and turn the job into a boot
+    pub fn boot_config(self, verbose: bool) -> anyhow::Result<BootConfig> {
+        let job = self.job;
+
+        let image_path = match &job.build {
+            Some(b) => {
+                build(&b.command, b.dir.as_deref())?;
+                Some(match &b.dir {
+                    Some(dir) => dir.join(&b.image),
+                    None => b.image.clone(),
+                })
+            }
+            None => job.image.clone(),
+        };
+        let image = image_path
+            .map(|path| {
+                fs::read(&path).with_context(|| format!("Couldn't read {}", path.display()))
+            })
+            .transpose()?;
+
+        Ok(BootConfig {
+            board: job.board,
+            label: None,
+            image,
+            verbose,
+            attach: false,
+            start_power: self.start_power,
+            power_off_delay: Duration::from_secs_f64(job.power.off_delay.unwrap_or(0.0)),
+            exit_power: self.exit_power,
+            fastboot_once: false,
+            criteria: self.criteria,
+            timeout: Duration::from_secs(job.timeouts.total),
+            inactivity_timeout: job.timeouts.inactivity.map(Duration::from_secs),
+            fastboot_timeout: job.timeouts.fastboot.map(Duration::from_secs),
+            status_interval: job.status_interval.map(Duration::from_secs),
+            milestones: self.milestones,
+            timing: false,
+            timing_json: job.artifacts.timing,
+            script: (!self.script.is_empty()).then_some(self.script),
+            exec: None,
+            ktap: job.ktap,
+            lava: job.lava,
+            log: self.log,
+            login: self.login,
+            power: PowerArgs {
+                power_log: job.artifacts.power_log,
+                power_interval: job.power_interval.unwrap_or(DEFAULT_POWER_INTERVAL),
+                ..PowerArgs::default()
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(yaml: &str) -> anyhow::Result<ValidJob> {
+        serde_yaml::from_str::<Job>(&format!("farm: lab\nboard: db845c\n{yaml}"))?.validate()
+    }
+
+    #[test]
+    fn steps_keep_their_whitespace() {
+        let job = job(r##"
+steps:
+  - send: "  "
+  - sendline: " ls -l "
+  - send: '\x03'
+  - expect: "# $"
+  - sleep: 0.5
+  - break
+"##)
+        .unwrap();
+
+        let sent: Vec<_> = job
+            .script
+            .iter()
+            .filter_map(|s| match &s.step {
+                script::Step::Send(buf) => Some(buf.as_slice()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sent, [&b"  "[..], b" ls -l \n", b"\x03"]);
+        assert!(matches!(&job.script[3].step, script::Step::Expect(re) if re.as_str() == "# $"));
+        assert!(matches!(job.script[4].step, script::Step::Sleep(d) if d.as_millis() == 500));
+        assert!(matches!(job.script[5].step, script::Step::Break));
+    }
+
+    #[test]
+    fn bad_steps() {
+        let e = job("steps:\n  - sleep: -1\n  - expect: \"(\"\n")
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(e.contains("steps[0]"), "{e}");
+        assert!(e.contains("steps[1]"), "{e}");
+    }
+}
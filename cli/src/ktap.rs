@@ -0,0 +1,343 @@
+use crate::results::{TestCase, TestResult};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Printk timestamps, as in `[    1.234567] `
+static PRINTK_TIME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\[\s*\d+\.\d+\] ?").unwrap());
+static VERSION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^K?TAP version \d+$").unwrap());
+static PLAN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^1\.\.(\d+)").unwrap());
+static SUBTEST: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^# Subtest: (.*)$").unwrap());
+static RESULT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(not ok|ok)\s+(\d+)\s*(?:-\s*)?([^#]*?)\s*(?:#\s*(.*))?$").unwrap()
+});
+
+/// A (sub)test document being parsed
+#[derive(Debug)]
+struct Level {
+    indent: usize,
+    name: Option<String>,
+    plan: Option<usize>,
+    count: usize,
+}
+
+/// Parses KTAP (and TAP 13/14) output, including nested subtests, out of the console lines
+#[derive(Debug, Default)]
+pub struct KtapParser {
+    stack: Vec<Level>,
+    /// Subtest name announced for the next level to be opened
+    pending_name: Option<String>,
+    results: Vec<TestCase>,
+    errors: Vec<String>,
+    /// Whether the top-level plan has been fulfilled
+    complete: bool,
+}
+
+impl KtapParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn results(&self) -> &[TestCase] {
+        &self.results
+    }
+
+    /// Plan mismatches and bail-outs
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    /// Whether every planned top-level test has reported
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.stack
+            .iter()
+            .filter_map(|l| l.name.as_deref())
+            .chain([name])
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    fn open(&mut self, indent: usize) {
+        self.stack.push(Level {
+            indent,
+            name: self.pending_name.take(),
+            plan: None,
+            count: 0,
+        });
+    }
+
+    fn close(&mut self) {
+        let Some(level) = self.stack.pop() else {
+            return;
+        };
+
+        if let Some(plan) = level.plan {
+            if plan != level.count {
+                let name = level.name.as_deref().unwrap_or("top level");
+                self.errors
+                    .push(format!("{name}: planned {plan} tests, {} ran", level.count));
+            }
+        }
+    }
+
+    /// Close the levels nested deeper than the given indentation
+    fn close_deeper(&mut self, indent: usize) {
+        while self.stack.last().is_some_and(|l| l.indent > indent) {
+            self.close();
+        }
+    }
+
+    /// The level at the given indentation, opened if need be
+    fn level_at(&mut self, indent: usize) -> &mut Level {
+        self.close_deeper(indent);
+        if self.stack.last().is_none_or(|l| l.indent < indent) {
+            self.open(indent);
+        }
+
+        self.stack.last_mut().unwrap()
+    }
+
+    pub fn line(&mut self, line: &str) {
+        let line = PRINTK_TIME.replace(line, "");
+        let content = line.trim_start();
+        let indent = line.len() - content.len();
+        let content = content.trim_end();
+
+        if VERSION.is_match(content) {
+            match self.stack.first() {
+                // A new document at the top level replaces the previous one
+                Some(top) if indent <= top.indent => {
+                    while !self.stack.is_empty() {
+                        self.close();
+                    }
+                    self.complete = false;
+                    self.open(indent);
+                }
+                Some(_) => {
+                    self.close_deeper(indent);
+                    if self.stack.last().is_some_and(|l| l.indent < indent) {
+                        self.open(indent);
+                    }
+                }
+                None => self.open(indent),
+            }
+            return;
+        }
+
+        // Nothing but KTAP documents is of any interest
+        if self.stack.is_empty() {
+            return;
+        }
+
+        if let Some(caps) = SUBTEST.captures(content) {
+            let name = caps[1].trim().to_string();
+            match self.stack.last_mut() {
+                Some(level) if level.indent == indent && level.count == 0 => {
+                    level.name = Some(name)
+                }
+                _ => self.pending_name = Some(name),
+            }
+        } else if let Some(caps) = PLAN.captures(content) {
+            let plan = caps[1].parse().ok();
+            self.level_at(indent).plan = plan;
+        } else if let Some(caps) = RESULT.captures(content) {
+            let has_subtests = self.stack.last().is_some_and(|l| l.indent > indent);
+            self.close_deeper(indent);
+
+            let directive = caps.get(4).map(|m| m.as_str().trim().to_string());
+            let upper = directive.as_deref().unwrap_or_default().to_uppercase();
+            let result = if upper.starts_with("SKIP") {
+                TestResult::Skip
+            } else if upper.starts_with("TODO") {
+                TestResult::Todo
+            } else if &caps[1] == "ok" {
+                TestResult::Pass
+            } else {
+                TestResult::Fail
+            };
+
+            let name = self.path(&caps[3]);
+            let level = self.level_at(indent);
+            level.count += 1;
+
+            let top_complete = self.stack.len() == 1
+                && self.stack[0]
+                    .plan
+                    .is_some_and(|plan| self.stack[0].count >= plan);
+            self.complete |= top_complete;
+
+            self.results.push(TestCase {
+                name,
+                result,
+                directive,
+                has_subtests,
+                measurement: None,
+            });
+        } else if content.starts_with("Bail out!") {
+            self.errors.push(content.to_string());
+            self.complete = true;
+        }
+    }
+
+    /// Wrap up whatever is still open, e.g. because the run ended halfway through
+    pub fn finish(&mut self) {
+        while !self.stack.is_empty() {
+            self.close();
+        }
+    }
+
+    /// Whether tests were run and all of them went fine
+    pub fn passed(&self) -> bool {
+        !self.results.is_empty()
+            && self.errors.is_empty()
+            && self.results.iter().all(|t| t.result != TestResult::Fail)
+    }
+
+    pub fn print_summary(&self) {
+        let leaves: Vec<_> = self.results.iter().filter(|t| !t.has_subtests).collect();
+        let count = |result| leaves.iter().filter(|t| t.result == result).count();
+
+        println!(
+            "\nKTAP: {} tests, {} passed, {} failed, {} skipped, {} todo",
+            leaves.len(),
+            count(TestResult::Pass),
+            count(TestResult::Fail),
+            count(TestResult::Skip),
+            count(TestResult::Todo),
+        );
+
+        for t in self.results.iter().filter(|t| t.result == TestResult::Fail) {
+            match &t.directive {
+                Some(directive) => println!("  {} {} # {directive}", t.result, t.name),
+                None => println!("  {} {}", t.result, t.name),
+            }
+        }
+
+        for e in &self.errors {
+            println!("  ERROR {e}");
+        }
+
+        if self.results.is_empty() {
+            println!("  No test results were seen");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> KtapParser {
+        let mut parser = KtapParser::new();
+        for line in text.lines() {
+            parser.line(line);
+        }
+        parser.finish();
+        parser
+    }
+
+    fn summary(parser: &KtapParser) -> Vec<(&str, TestResult)> {
+        parser
+            .results()
+            .iter()
+            .map(|t| (t.name.as_str(), t.result))
+            .collect()
+    }
+
+    #[test]
+    fn nested_subtests() {
+        let parser = parse(
+            "[    1.000000] Booting...
+[    1.100000] KTAP version 1
+[    1.100001] 1..2
+[    1.100002]     KTAP version 1
+[    1.100003]     # Subtest: example
+[    1.100004]     1..2
+[    1.100005]     ok 1 example_simple_test
+[    1.100006]     not ok 2 example_failing_test
+[    1.100007] not ok 1 example
+[    1.100008] ok 2 other",
+        );
+
+        assert_eq!(
+            summary(&parser),
+            [
+                ("example.example_simple_test", TestResult::Pass),
+                ("example.example_failing_test", TestResult::Fail),
+                ("example", TestResult::Fail),
+                ("other", TestResult::Pass),
+            ]
+        );
+        assert!(parser.results()[2].has_subtests);
+        assert!(!parser.results()[3].has_subtests);
+        assert!(parser.errors().is_empty());
+        assert!(parser.is_complete());
+        assert!(!parser.passed());
+    }
+
+    #[test]
+    fn plan_mismatch() {
+        let parser = parse(
+            "KTAP version 1
+1..3
+ok 1 first
+ok 2 second",
+        );
+
+        assert_eq!(parser.results().len(), 2);
+        assert_eq!(parser.errors(), ["top level: planned 3 tests, 2 ran"]);
+        assert!(!parser.is_complete());
+        assert!(!parser.passed());
+    }
+
+    #[test]
+    fn skip_and_todo() {
+        let parser = parse(
+            "TAP version 13
+1..3
+ok 1 - probe # SKIP no hardware
+not ok 2 - feature # TODO not implemented
+ok 3 - works",
+        );
+
+        assert_eq!(
+            summary(&parser),
+            [
+                ("probe", TestResult::Skip),
+                ("feature", TestResult::Todo),
+                ("works", TestResult::Pass),
+            ]
+        );
+        assert_eq!(
+            parser.results()[1].directive.as_deref(),
+            Some("TODO not implemented")
+        );
+        assert!(parser.passed());
+    }
+
+    #[test]
+    fn missing_plan() {
+        let parser = parse(
+            "KTAP version 1
+ok 1 first
+ok 2 second",
+        );
+
+        assert_eq!(parser.results().len(), 2);
+        assert!(parser.errors().is_empty());
+        assert!(!parser.is_complete());
+        assert!(parser.passed());
+    }
+
+    #[test]
+    fn ignores_lines_outside_documents() {
+        let parser = parse("ok 1 not a test\n1..1");
+
+        assert!(parser.results().is_empty());
+        assert!(!parser.passed());
+    }
+}
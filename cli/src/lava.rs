@@ -0,0 +1,253 @@
+use crate::results::{TestCase, TestResult};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+static SIGNAL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<LAVA_SIGNAL_([A-Z]+)\s*([^>]*)>").unwrap());
+
+/// Collects the results reported through LAVA test shell signals on the console, e.g.
+/// `<LAVA_SIGNAL_TESTCASE TEST_CASE_ID=boot RESULT=pass>`
+#[derive(Debug, Default)]
+pub struct LavaParser {
+    /// Test runs started with STARTRUN and not ended yet
+    open_runs: Vec<String>,
+    /// The test run the results are attributed to
+    run: Option<String>,
+    /// The test set started with TESTSET START
+    set: Option<String>,
+    results: Vec<TestCase>,
+    errors: Vec<String>,
+}
+
+impl LavaParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn results(&self) -> &[TestCase] {
+        &self.results
+    }
+
+    /// Test runs that were ended without being started, or never ended
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    pub fn line(&mut self, line: &str) {
+        for caps in SIGNAL.captures_iter(line) {
+            let args = caps[2].trim();
+
+            match &caps[1] {
+                "STARTRUN" => {
+                    let id = args
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string();
+                    self.open_runs.push(id.clone());
+                    self.run = Some(id);
+                    self.set = None;
+                }
+                "ENDRUN" => {
+                    let id = args.split_whitespace().next().unwrap_or_default();
+                    match self.open_runs.iter().position(|r| r == id) {
+                        Some(i) => {
+                            self.open_runs.remove(i);
+                        }
+                        None => self
+                            .errors
+                            .push(format!("test run {id} ended without being started")),
+                    }
+                    self.run = self.open_runs.last().cloned();
+                    self.set = None;
+                }
+                "TESTSET" => match args.split_once(char::is_whitespace) {
+                    Some(("START", name)) => self.set = Some(name.trim().to_string()),
+                    _ => self.set = None,
+                },
+                "TESTCASE" => self.testcase(args),
+                // STARTTC/ENDTC only delimit the output of a test case
+                _ => (),
+            }
+        }
+    }
+
+    fn testcase(&mut self, args: &str) {
+        let fields: HashMap<_, _> = args
+            .split_whitespace()
+            .filter_map(|kv| kv.split_once('='))
+            .collect();
+
+        let Some(id) = fields.get("TEST_CASE_ID") else {
+            self.errors
+                .push(format!("test case without TEST_CASE_ID: `{args}`"));
+            return;
+        };
+
+        let (result, directive) = match fields.get("RESULT").map(|r| r.to_lowercase()).as_deref() {
+            Some("pass") => (TestResult::Pass, None),
+            Some("fail") => (TestResult::Fail, None),
+            Some("skip") => (TestResult::Skip, None),
+            Some(other) => (TestResult::Skip, Some(format!("result `{other}`"))),
+            None => (TestResult::Skip, Some("no result".to_string())),
+        };
+
+        let measurement = fields
+            .get("MEASUREMENT")
+            .map(|m| match fields.get("UNITS") {
+                Some(units) => format!("{m} {units}"),
+                None => m.to_string(),
+            });
+
+        let name = [self.run.as_deref(), self.set.as_deref(), Some(id)]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(".");
+
+        self.results.push(TestCase {
+            name,
+            result,
+            directive,
+            has_subtests: false,
+            measurement,
+        });
+    }
+
+    /// Wrap up, e.g. because the run ended while a test run was still going
+    pub fn finish(&mut self) {
+        for id in self.open_runs.drain(..) {
+            self.errors.push(format!("test run {id} never ended"));
+        }
+    }
+
+    /// Whether tests were run and none of them failed
+    pub fn passed(&self) -> bool {
+        !self.results.is_empty()
+            && self.errors.is_empty()
+            && self.results.iter().all(|t| t.result != TestResult::Fail)
+    }
+
+    pub fn print_summary(&self) {
+        let count = |result| self.results.iter().filter(|t| t.result == result).count();
+
+        println!(
+            "\nLAVA: {} test cases, {} passed, {} failed, {} skipped",
+            self.results.len(),
+            count(TestResult::Pass),
+            count(TestResult::Fail),
+            count(TestResult::Skip),
+        );
+
+        for t in &self.results {
+            match (&t.measurement, t.result) {
+                (Some(m), _) => println!("  {} {} = {m}", t.result, t.name),
+                (None, TestResult::Fail) => println!("  {} {}", t.result, t.name),
+                _ => (),
+            }
+        }
+
+        for e in &self.errors {
+            println!("  ERROR {e}");
+        }
+
+        if self.results.is_empty() {
+            println!("  No test results were seen");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> LavaParser {
+        let mut parser = LavaParser::new();
+        for line in text.lines() {
+            parser.line(line);
+        }
+        parser.finish();
+        parser
+    }
+
+    #[test]
+    fn case_results() {
+        let parser = parse(
+            "<LAVA_SIGNAL_STARTRUN 0_smoke 42>
+<LAVA_SIGNAL_TESTCASE TEST_CASE_ID=boot RESULT=pass>
+[   12.345678] <LAVA_SIGNAL_TESTCASE TEST_CASE_ID=network RESULT=FAIL>
+<LAVA_SIGNAL_TESTCASE TEST_CASE_ID=gpu RESULT=skip>
+<LAVA_SIGNAL_TESTCASE TEST_CASE_ID=odd RESULT=unknown>
+<LAVA_SIGNAL_ENDRUN 0_smoke 42>",
+        );
+
+        let results: Vec<_> = parser
+            .results()
+            .iter()
+            .map(|t| (t.name.as_str(), t.result, t.directive.as_deref()))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                ("0_smoke.boot", TestResult::Pass, None),
+                ("0_smoke.network", TestResult::Fail, None),
+                ("0_smoke.gpu", TestResult::Skip, None),
+                ("0_smoke.odd", TestResult::Skip, Some("result `unknown`")),
+            ]
+        );
+        assert!(parser.errors().is_empty());
+        assert!(!parser.passed());
+    }
+
+    #[test]
+    fn measurements() {
+        let parser = parse(
+            "<LAVA_SIGNAL_TESTCASE TEST_CASE_ID=boot-time RESULT=pass MEASUREMENT=4.2 UNITS=s>
+<LAVA_SIGNAL_TESTCASE TEST_CASE_ID=count RESULT=pass MEASUREMENT=7>",
+        );
+
+        let measurements: Vec<_> = parser
+            .results()
+            .iter()
+            .map(|t| t.measurement.as_deref())
+            .collect();
+        assert_eq!(measurements, [Some("4.2 s"), Some("7")]);
+        assert!(parser.passed());
+    }
+
+    #[test]
+    fn test_sets() {
+        let parser = parse(
+            "<LAVA_SIGNAL_STARTRUN 1_suite 7>
+<LAVA_SIGNAL_TESTSET START storage>
+<LAVA_SIGNAL_TESTCASE TEST_CASE_ID=read RESULT=pass>
+<LAVA_SIGNAL_TESTSET STOP>
+<LAVA_SIGNAL_TESTCASE TEST_CASE_ID=after RESULT=pass>
+<LAVA_SIGNAL_ENDRUN 1_suite 7>
+<LAVA_SIGNAL_TESTCASE TEST_CASE_ID=outside RESULT=pass>",
+        );
+
+        let names: Vec<_> = parser.results().iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["1_suite.storage.read", "1_suite.after", "outside"]);
+    }
+
+    #[test]
+    fn unbalanced_runs() {
+        let parser = parse(
+            "<LAVA_SIGNAL_ENDRUN nope 1>
+<LAVA_SIGNAL_STARTRUN open 2>
+<LAVA_SIGNAL_TESTCASE RESULT=pass>",
+        );
+
+        assert_eq!(
+            parser.errors(),
+            [
+                "test run nope ended without being started",
+                "test case without TEST_CASE_ID: `RESULT=pass`",
+                "test run open never ended",
+            ]
+        );
+        assert!(!parser.passed());
+    }
+}
@@ -1,140 +1,569 @@
-use anyhow::Context;
-use clap::Parser;
+use anyhow::{bail, Context};
+use clap::{CommandFactory, Parser, Subcommand};
 use colored::Colorize;
-use russh::client::Msg;
-use sk8brd::ssh::{ssh_connect, SSH_BUFFER_SIZE};
-use sk8brd::{
-    console_print, parse_recv_msg, print_string_msg, select_brd, send_ack, send_image, todo,
-    Sk8brdMsgs, CDBA_SERVER_BIN_NAME, MSG_HDR_SIZE,
-};
+use regex::Regex;
+use sk8brd::bootimg;
+use sk8brd::config::{Config, FarmArgs};
+use sk8brd::log::LogArgs;
+use sk8brd::login::LoginArgs;
+use sk8brd::power::{PowerArgs, PowerPolicyArgs};
+use std::ffi::OsString;
 use std::fs;
-use std::io::{stdout, Write};
-use std::sync::Arc;
-use std::time::{Duration, SystemTime};
-use tokio::io::AsyncReadExt;
-use tokio::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+mod bisect;
+mod boot;
+mod cdba;
+mod control;
+mod criteria;
+mod exec;
+mod job;
+mod ktap;
+mod lava;
+mod matrix;
+mod parallel;
+mod report;
+mod results;
+mod script;
+mod session;
+mod soak;
+mod timeouts;
+mod timing;
+use bisect::BisectArgs;
+use boot::{boot_once, BootConfig, Outcome};
+use control::{PowerAction, Switch};
+use criteria::Criteria;
+use exec::{ExecArgs, ExecRunner};
+use matrix::MatrixArgs;
+use report::RunReport;
+use session::{Farm, Session};
+use soak::RepeatArgs;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    #[arg(short)]
-    farm: String,
-
-    #[arg(short, default_value_t = String::from("22"))]
-    port: String,
+    /// Where to find the server; the farm is required unless a job file names it
+    #[command(flatten)]
+    farm: FarmArgs,
 
-    #[arg(short, default_value_t = String::from(""))]
-    board: String,
+    /// Board(s) to boot, comma separated or repeated, `*` and `?` match the farm's boards;
+    /// the boards are listed if none is given
+    #[arg(short = 'b', value_delimiter = ',', env = "SK8BRD_BOARD")]
+    boards: Vec<String>,
 
-    #[arg(short)]
-    image_path: String,
+    /// Boot image to send; without one, fastboot is told to continue
+    #[arg(short, env = "SK8BRD_IMAGE")]
+    image_path: Option<String>,
 
-    #[arg(short, default_value_t = String::from("cdba"))]
-    user: String,
+    /// Replace the kernel command line in the boot image's header
+    #[arg(long)]
+    cmdline: Option<String>,
 
     #[arg(short, default_value_t = false)]
     verbose: bool,
 
+    #[command(flatten)]
+    power_policy: PowerPolicyArgs,
+
+    /// End the run once fastboot shows up again after the boot, rather than sending the image
+    /// again
+    #[arg(long, default_value_t = false)]
+    fastboot_once: bool,
+
+    /// Total deadline in seconds, counted from power on
     #[arg(short, default_value_t = 60)]
     timeout: u64,
+
+    /// Give up after this many seconds without console output
+    #[arg(long)]
+    inactivity_timeout: Option<u64>,
+
+    /// Give up if fastboot doesn't show up this many seconds after power on
+    #[arg(long)]
+    fastboot_timeout: Option<u64>,
+
+    /// Ask the server for a status update (voltage, current, ...) every this many seconds
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    status_interval: Option<u64>,
+
+    /// Exit successfully once a console line matches (repeatable)
+    #[arg(long, value_parser = Regex::new)]
+    pass: Vec<Regex>,
+
+    /// Exit with a failure once a console line matches (repeatable)
+    #[arg(long, value_parser = Regex::new)]
+    fail: Vec<Regex>,
+
+    /// Stop waiting once a console line matches
+    #[arg(long, value_parser = Regex::new)]
+    until: Option<Regex>,
+
+    /// Record when a console line first matches, for the timing report (repeatable)
+    #[arg(long, value_parser = Regex::new)]
+    milestone: Vec<Regex>,
+
+    /// Print a boot timing report at exit
+    #[arg(long, default_value_t = false)]
+    timing: bool,
+
+    /// Write the boot timing report as JSON
+    #[arg(long)]
+    timing_json: Option<PathBuf>,
+
+    /// Run an expect-style script (send/sendline/expect/timeout/sleep/break) once the image is sent
+    #[arg(long)]
+    script: Option<PathBuf>,
+
+    /// Parse KTAP/kselftest results from the console and exit according to them
+    #[arg(long, default_value_t = false)]
+    ktap: bool,
+
+    /// Collect LAVA test signals (LAVA_SIGNAL_TESTCASE etc.) from the console and exit according to them
+    #[arg(long, default_value_t = false)]
+    lava: bool,
+
+    /// Write a JUnit XML report of the run
+    #[arg(long)]
+    junit: Option<PathBuf>,
+
+    /// Write a TAP report of the run
+    #[arg(long)]
+    tap: Option<PathBuf>,
+
+    #[command(flatten)]
+    repeat: RepeatArgs,
+
+    #[command(flatten)]
+    log: LogArgs,
+
+    #[command(flatten)]
+    login: LoginArgs,
+
+    #[command(flatten)]
+    power: PowerArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let quit = Arc::new(Mutex::new(false));
-    let mut buf = [0u8; SSH_BUFFER_SIZE];
-    let mut time: SystemTime = SystemTime::now();
-    let mut hdr_buf = [0u8; MSG_HDR_SIZE];
-    let args = Args::parse();
-
-    let fastboot_image = fs::read(args.image_path).expect("boot image not found");
-
-    println!("sk8brd-cli {}", env!("CARGO_PKG_VERSION"));
-
-    let chan = Arc::new(Mutex::new(
-        ssh_connect(&format!("{}:{}", args.farm, args.port), args.user).await?,
-    ));
-    (*chan.lock().await)
-        .exec(true, CDBA_SERVER_BIN_NAME)
-        .await
-        .with_context(|| format!("Couldn't execute {CDBA_SERVER_BIN_NAME} on remote server"))?;
-
-    let mut server_stdin = Arc::new(Mutex::new((*chan.lock().await).make_writer()));
-    let (server_stdout, server_stderr) = sk8brd::ssh::into_streams::<Msg>(chan).await;
-    let server_stdout = Arc::new(Mutex::new(server_stdout));
-    let server_stderr = Arc::new(Mutex::new(server_stderr));
-
-    if args.board.is_empty() {
-        send_ack(&mut server_stdin, Sk8brdMsgs::MsgListDevices).await?;
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a shell command on the board, passing on its output and exit status
+    Exec(ExecArgs),
+    /// Run a job described by a YAML file, validated before connecting to the farm
+    Run { job: PathBuf },
+    /// Build and boot the current commit for `git bisect run`: exits 0 (good), 1 (bad) or 125 (skip)
+    BisectRun(BisectArgs),
+    /// Boot every image on every board (-b), writing an HTML report of the lot
+    Matrix(MatrixArgs),
+    /// Show what the server tells about the board (-b)
+    Info {
+        /// Print the information as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// List the farm's boards
+    List {
+        /// Print the list as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+    /// Switch the board's (-b) power
+    Power {
+        #[arg(value_enum)]
+        action: PowerAction,
+    },
+    /// Switch the board's (-b) USB VBUS
+    Vbus {
+        #[arg(value_enum)]
+        state: Switch,
+    },
+    /// Send a break to the board's (-b) console
+    Break,
+    /// Print the board's (-b) console, without touching its power, until interrupted
+    Console,
+    /// Boot the board(s) (-b), as when no subcommand is given
+    Boot,
+}
+
+/// The farm given on the command line, the environment or the config file
+fn flags_farm(args: &Args) -> Option<Farm> {
+    args.farm.farm()
+}
+
+/// Merge in the config file: flags and environment variables take precedence, board aliases
+/// are turned into the farm's names and, with a single board, provide its defaults
+fn apply_config(args: &mut Args, config: &Config) -> anyhow::Result<()> {
+    let alias = match &args.boards[..] {
+        [board] => config.alias(board),
+        _ => None,
+    };
+
+    args.farm.resolve(config, alias);
+    if let Some(alias) = alias {
+        args.image_path = args
+            .image_path
+            .take()
+            .or_else(|| alias.image().map(|p| p.to_string_lossy().into_owned()));
+        args.cmdline = args.cmdline.take().or(alias.cmdline.clone());
+        alias.apply_login(&mut args.login)?;
+    }
+    args.boards = args.boards.iter().map(|b| config.board_name(b)).collect();
+
+    Ok(())
+}
+
+fn read_image(path: &str, cmdline: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    let mut image = fs::read(path).with_context(|| format!("Couldn't read {path}"))?;
+    if let Some(cmdline) = cmdline {
+        bootimg::set_cmdline(&mut image, cmdline)?;
+    }
+
+    Ok(image)
+}
+
+/// The first run that didn't succeed decides
+fn overall_outcome(reports: &[RunReport]) -> Outcome {
+    reports
+        .iter()
+        .map(|r| r.outcome)
+        .find(|o| !o.is_success())
+        .unwrap_or(Outcome::Pass)
+}
+
+/// Boot the board as configured and write the reports of the run
+async fn boot_and_report(
+    farm: &Farm,
+    mut config: BootConfig,
+    boards: &[String],
+    repeat: &RepeatArgs,
+    junit: Option<&Path>,
+    tap: Option<&Path>,
+) -> anyhow::Result<Outcome> {
+    eprintln!("sk8brd-cli {}", env!("CARGO_PKG_VERSION"));
+
+    let boards = parallel::expand_boards(farm, boards).await?;
+    config.board = boards.first().cloned().unwrap_or_default();
+
+    let reports = if boards.len() > 1 {
+        let runs = parallel::run(farm, &config, &boards, repeat).await;
+        if repeat.is_active() {
+            for (board, reports) in &runs {
+                println!("\n{board}:");
+                soak::print_summary(reports);
+            }
+        }
+        parallel::print_summary(&runs);
+        runs.into_iter().flat_map(|(_, reports)| reports).collect()
+    } else if repeat.is_active() {
+        let reports = soak::run(farm, config, repeat).await?;
+        soak::print_summary(&reports);
+        reports
     } else {
-        select_brd(&mut server_stdin, &args.board).await?;
+        vec![boot_once(farm, config).await?]
+    };
+    report::write_reports(junit, tap, &reports)?;
+
+    // ssh_disconnect(&mut sess).await?;
+
+    eprintln!("\nGoodbye");
+    Ok(overall_outcome(&reports))
+}
+
+async fn run_job(
+    path: &Path,
+    verbose: bool,
+    repeat: &RepeatArgs,
+    config: &Config,
+) -> anyhow::Result<Outcome> {
+    let job = job::load(path)?;
+    let mut farm = FarmArgs {
+        farm: Some(job.job.farm.clone()),
+        port: job.job.port.map(|p| p.to_string()),
+        user: job.job.user.clone(),
+        ..FarmArgs::default()
+    };
+    farm.resolve(config, None);
+    let farm = farm.farm().context("The job names no farm")?;
+    let junit = job.job.artifacts.junit.clone();
+    let tap = job.job.artifacts.tap.clone();
+
+    let config = job.boot_config(verbose)?;
+    let boards = [config.board.clone()];
+    boot_and_report(
+        &farm,
+        config,
+        &boards,
+        repeat,
+        junit.as_deref(),
+        tap.as_deref(),
+    )
+    .await
+}
+
+/// The boot described by the command line flags
+fn flags_config(
+    args: Args,
+    image: Option<Vec<u8>>,
+    exec: Option<ExecRunner>,
+    attach: bool,
+) -> anyhow::Result<BootConfig> {
+    let script = args.script.as_deref().map(script::load).transpose()?;
+
+    Ok(BootConfig {
+        board: String::new(),
+        label: None,
+        image,
+        verbose: args.verbose,
+        attach,
+        start_power: args.power_policy.start(),
+        power_off_delay: args.power_policy.off_delay(),
+        exit_power: args.power_policy.exit_power,
+        fastboot_once: args.fastboot_once,
+        criteria: Criteria {
+            pass: args.pass,
+            fail: args.fail,
+            until: args.until,
+        },
+        timeout: Duration::from_secs(args.timeout),
+        inactivity_timeout: args.inactivity_timeout.map(Duration::from_secs),
+        fastboot_timeout: args.fastboot_timeout.map(Duration::from_secs),
+        status_interval: args.status_interval.map(Duration::from_secs),
+        milestones: args.milestone,
+        timing: args.timing,
+        timing_json: args.timing_json,
+        script,
+        exec,
+        ktap: args.ktap,
+        lava: args.lava,
+        log: args.log,
+        login: args.login,
+        power: args.power,
+    })
+}
+
+/// Build and boot the checked out commit, telling `git bisect run` what to make of it
+async fn run_bisect(args: Args, bisect_args: &BisectArgs) -> anyhow::Result<Outcome> {
+    let farm = flags_farm(&args).context("The farm (-f <FARM>) is required")?;
+    let image_path = args
+        .image_path
+        .clone()
+        .context("The image built by --build (-i <IMAGE_PATH>) is required")?;
+    let [board] = &args.boards[..] else {
+        bail!("Bisecting takes a single board (-b <BOARD>)");
+    };
+    // Without one, a board hanging silently until the deadline would count as good
+    if args.pass.is_empty() {
+        bail!("Bisecting takes a pattern telling a good boot (--pass <REGEX>)");
     }
+    let board = board.clone();
+    let (junit, tap) = (args.junit.clone(), args.tap.clone());
+
+    eprintln!("sk8brd-cli {}", env!("CARGO_PKG_VERSION"));
+
+    if let Err(e) = job::build(&bisect_args.build, None) {
+        eprintln!("{}", format!("Error: {e:#}, skipping").yellow());
+        return Ok(Outcome::Exited(bisect::SKIP));
+    }
+    let image = read_image(&image_path, args.cmdline.as_deref())?;
+
+    let mut config = flags_config(args, Some(image), None, false)?;
+    config.board = board;
+    let (reports, outcome) = bisect::run(&farm, config, bisect_args).await?;
+    report::write_reports(junit.as_deref(), tap.as_deref(), &reports)?;
+
+    eprintln!("\nGoodbye");
+    Ok(outcome)
+}
+
+async fn run_matrix(args: Args, matrix_args: &MatrixArgs) -> anyhow::Result<Outcome> {
+    let Some(farm) = flags_farm(&args) else {
+        bail!("The farm (-f <FARM>) is required");
+    };
+    let boards = args.boards.clone();
+    let (junit, tap) = (args.junit.clone(), args.tap.clone());
 
-    // Msg handler
-    // Read the message header first
-    while time.elapsed()? < Duration::from_secs(args.timeout) {
-        // Stream of "blue text" - status updates from the server
-        if let Ok(bytes_read) = (*server_stderr.lock().await).read(&mut buf).await {
-            let s = String::from_utf8_lossy(&buf[..bytes_read]);
-            print!(
-                "{}\r",
-                s.split('\n').collect::<Vec<_>>().join("\r\n").blue()
-            );
-            stdout().flush()?;
+    eprintln!("sk8brd-cli {}", env!("CARGO_PKG_VERSION"));
+
+    let boards = parallel::expand_boards(&farm, &boards).await?;
+    if boards.is_empty() {
+        bail!("The matrix takes at least one board (-b <BOARD>)");
+    }
+
+    let config = flags_config(args, None, None, false)?;
+    let cells = matrix::run(&farm, &config, &boards, matrix_args).await?;
+    matrix::write_report(
+        &matrix_args.output,
+        &farm,
+        &cells,
+        &boards,
+        &matrix_args.images,
+    )?;
+
+    let reports: Vec<_> = cells.into_iter().map(|c| c.report).collect();
+    report::write_reports(junit.as_deref(), tap.as_deref(), &reports)?;
+
+    eprintln!("\nGoodbye");
+    Ok(overall_outcome(&reports))
+}
+
+async fn list(farm: &Farm, json: bool, timeout: Duration) -> anyhow::Result<Outcome> {
+    control::with_timeout(timeout, async {
+        let list = Session::connect(farm).await?.list_boards().await?;
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(list.boards())?);
+        } else {
+            print!("{}", list.table());
         }
 
-        if (*server_stdout.lock().await)
-            .read_exact(&mut hdr_buf)
-            .await
-            .is_ok()
-        {
-            let msg = parse_recv_msg(&hdr_buf);
-            let mut msgbuf = vec![0u8; msg.len as usize];
-
-            // Now read the actual data...
-            (*server_stderr.lock().await)
-                .read_exact(&mut msgbuf)
-                .await?;
-
-            // ..and process it
-            match msg.r#type.try_into() {
-                Ok(Sk8brdMsgs::MsgSelectBoard) => {
-                    send_ack(&mut server_stdin, Sk8brdMsgs::MsgPowerOn).await?
-                }
-                Ok(Sk8brdMsgs::MsgConsole) => {
-                    if args.verbose {
-                        console_print(&msgbuf).await
-                    }
-                }
-                Ok(Sk8brdMsgs::MsgPowerOn) => {
-                    // Refresh the timer so that the timeout actually makes sense
-                    time = SystemTime::now();
-                }
-                Ok(Sk8brdMsgs::MsgFastbootPresent) => {
-                    if !msgbuf.is_empty() && msgbuf[0] != 0 {
-                        send_image(&mut server_stdin, &fastboot_image, &quit).await?
-                    }
-                }
-                Ok(Sk8brdMsgs::MsgFastbootDownload) => (),
-                Ok(Sk8brdMsgs::MsgListDevices) => {
-                    print_string_msg(&msgbuf);
-                    if msgbuf.is_empty() {
-                        break;
-                    }
-                }
-
-                // Ignore all other valid messages
-                Ok(_) => (),
-                Err(e) => todo!("Received unknown/invalid message: `{e}`"),
-            };
+        Ok(())
+    })
+    .await
+}
+
+async fn info(
+    farm: &Farm,
+    boards: &[String],
+    json: bool,
+    timeout: Duration,
+) -> anyhow::Result<Outcome> {
+    let [board] = boards else {
+        bail!("Board information is shown for a single board (-b <BOARD>)");
+    };
+
+    control::with_timeout(timeout, async {
+        let info = Session::connect(farm).await?.board_info(board).await?;
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&info)?);
+        } else {
+            println!("{info}");
         }
+
+        Ok(())
+    })
+    .await
+}
+
+async fn run(mut args: Args) -> anyhow::Result<Outcome> {
+    let config = Config::load(args.farm.config.as_deref()).and_then(|config| {
+        apply_config(&mut args, &config)?;
+        Ok(config)
+    });
+    let config = match (config, &args.command) {
+        (Ok(config), _) => config,
+        // A broken config file is no reason to blame the commit either
+        (Err(e), Some(Command::BisectRun(bisect_args))) => {
+            eprintln!("{}", format!("Error: {e:#}").red());
+            return Ok(bisect_args.on_error.outcome());
+        }
+        (Err(e), _) => return Err(e),
+    };
+
+    let (exec, attach) = match &args.command {
+        Some(Command::Run { job }) => {
+            return run_job(job, args.verbose, &args.repeat, &config).await
+        }
+        Some(Command::BisectRun(bisect_args)) => {
+            // Whatever goes wrong here, the commit isn't to blame
+            let bisect_args = bisect_args.clone();
+            return Ok(run_bisect(args, &bisect_args).await.unwrap_or_else(|e| {
+                eprintln!("{}", format!("Error: {e:#}").red());
+                bisect_args.on_error.outcome()
+            }));
+        }
+        Some(Command::Matrix(matrix_args)) => {
+            let matrix_args = matrix_args.clone();
+            return run_matrix(args, &matrix_args).await;
+        }
+        Some(Command::Exec(exec_args)) => (Some(ExecRunner::new(exec_args)), exec_args.attach),
+        Some(
+            Command::List { .. }
+            | Command::Info { .. }
+            | Command::Power { .. }
+            | Command::Vbus { .. }
+            | Command::Break
+            | Command::Console
+            | Command::Boot,
+        )
+        | None => (None, false),
+    };
+
+    let Some(farm) = flags_farm(&args) else {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "the farm (-f <FARM>) is required",
+            )
+            .exit();
+    };
+    let timeout = Duration::from_secs(args.timeout);
+    match args.command {
+        Some(Command::List { json }) => return list(&farm, json, timeout).await,
+        Some(Command::Info { json }) => return info(&farm, &args.boards, json, timeout).await,
+        Some(Command::Power { action }) => {
+            let off_delay = args.power_policy.off_delay();
+            return control::power(&farm, &args.boards, action, off_delay, timeout).await;
+        }
+        Some(Command::Vbus { state }) => {
+            return control::vbus(&farm, &args.boards, state, timeout).await
+        }
+        Some(Command::Break) => return control::console_break(&farm, &args.boards, timeout).await,
+        Some(Command::Console) => return control::console(&farm, &args.boards).await,
+        Some(Command::Boot) if args.boards.is_empty() => {
+            bail!("Booting takes at least one board (-b <BOARD>)")
+        }
+        _ => (),
+    }
+    if args.boards.is_empty() {
+        return list(&farm, false, timeout).await;
     }
 
-    // Power off the board on goodbye
-    send_ack(&mut server_stdin, Sk8brdMsgs::MsgPowerOff).await?;
+    let (boards, repeat) = (args.boards.clone(), args.repeat.clone());
+    let (junit, tap) = (args.junit.clone(), args.tap.clone());
 
-    // ssh_disconnect(&mut sess).await?;
+    let image = args
+        .image_path
+        .as_deref()
+        .map(|path| read_image(path, args.cmdline.as_deref()))
+        .transpose()?;
 
-    println!("\nGoodbye");
-    Ok(())
+    let config = flags_config(args, image, exec, attach)?;
+    boot_and_report(
+        &farm,
+        config,
+        &boards,
+        &repeat,
+        junit.as_deref(),
+        tap.as_deref(),
+    )
+    .await
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let argv: Vec<OsString> = std::env::args_os().collect();
+    let args = if cdba::is_compat(&argv) {
+        Args::parse_from(cdba::translate(argv))
+    } else {
+        Args::parse_from(argv)
+    };
+
+    let exec = matches!(args.command, Some(Command::Exec(_)));
+    let outcome = match run(args).await {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            eprintln!("{}", format!("Error: {e:#}").red());
+            Outcome::Error
+        }
+    };
+
+    if exec {
+        exec::outcome(outcome).into()
+    } else {
+        outcome.into()
+    }
 }
@@ -0,0 +1,344 @@
+use crate::boot::{boot_once, run_failed, BootConfig, Outcome};
+use crate::report::{xml_escape, RunReport};
+use crate::session::Farm;
+use anyhow::Context;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// An image of the matrix, given as `PATH` or `NAME=PATH`
+#[derive(Clone, Debug)]
+pub struct MatrixImage {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl FromStr for MatrixImage {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.split_once('=') {
+            Some((name, path)) => Self {
+                name: name.to_string(),
+                path: path.into(),
+            },
+            None => Self {
+                name: Path::new(s)
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                path: s.into(),
+            },
+        })
+    }
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct MatrixArgs {
+    /// Image to boot on every board, as PATH or NAME=PATH (repeatable)
+    #[arg(long = "image", required = true)]
+    pub images: Vec<MatrixImage>,
+
+    /// How many boards are booted at the same time
+    #[arg(long, default_value_t = 4)]
+    pub jobs: usize,
+
+    /// Directory to write the HTML report and the console logs to
+    #[arg(long, default_value = "sk8brd-matrix")]
+    pub output: PathBuf,
+}
+
+/// A single run of the matrix
+pub struct Cell {
+    pub board: String,
+    pub image: String,
+    /// Position of the image on the command line, as image names needn't be unique
+    pub image_index: usize,
+    pub report: RunReport,
+}
+
+/// Turn a name into something usable as is in a file name and a URL
+fn file_name_part(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+impl Cell {
+    /// `<board>-<image number>-<image>.log`
+    fn log_name(&self) -> String {
+        format!(
+            "{}-{}-{}.log",
+            file_name_part(&self.board),
+            self.image_index + 1,
+            file_name_part(&self.image)
+        )
+    }
+}
+
+/// Boot every image on every board. A board boots one image after the other, while up to
+/// `jobs` boards are busy at the same time.
+pub async fn run(
+    farm: &Farm,
+    config: &BootConfig,
+    boards: &[String],
+    args: &MatrixArgs,
+) -> anyhow::Result<Vec<Cell>> {
+    let images = args
+        .images
+        .iter()
+        .map(|i| {
+            fs::read(&i.path)
+                .map(|data| (i.name.clone(), data))
+                .with_context(|| format!("Couldn't read {}", i.path.display()))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let images = Arc::new(images);
+
+    let slots = Arc::new(Semaphore::new(args.jobs.max(1)));
+    let mut tasks = JoinSet::new();
+    let mut ids = HashMap::new();
+
+    for (i, board) in boards.iter().enumerate() {
+        let slots = Arc::clone(&slots);
+        let (farm, config, board) = (farm.clone(), config.clone(), board.clone());
+        let images = Arc::clone(&images);
+
+        let task = tasks.spawn(async move {
+            let mut cells = vec![];
+            for (j, (name, data)) in images.iter().enumerate() {
+                // The semaphore is never closed, so this always gets a permit
+                let _permit = slots.acquire().await;
+
+                let mut config = config.with_suffix(&format!(
+                    "{}-{}-{}",
+                    file_name_part(&board),
+                    j + 1,
+                    file_name_part(name)
+                ));
+                let label = format!("{board}/{name}");
+                config.board = board.clone();
+                config.label = Some(label.clone());
+                config.image = Some(data.clone());
+
+                let start = Instant::now();
+                let mut report = boot_once(&farm, config)
+                    .await
+                    .unwrap_or_else(|e| run_failed(&label, start, e));
+                report.board = label;
+                cells.push(Cell {
+                    board: board.clone(),
+                    image: name.clone(),
+                    image_index: j,
+                    report,
+                });
+            }
+
+            cells
+        });
+        ids.insert(task.id(), (i, Instant::now()));
+    }
+
+    // A board breaking off mustn't take the rest of the matrix down along with it
+    let mut cells: Vec<Vec<Cell>> = boards.iter().map(|_| vec![]).collect();
+    while let Some(joined) = tasks.join_next_with_id().await {
+        match joined {
+            Ok((id, board_cells)) => cells[ids[&id].0] = board_cells,
+            Err(e) => {
+                let (i, start) = ids[&e.id()];
+                let e = anyhow::Error::from(e);
+                cells[i] = args
+                    .images
+                    .iter()
+                    .enumerate()
+                    .map(|(j, image)| Cell {
+                        board: boards[i].clone(),
+                        image: image.name.clone(),
+                        image_index: j,
+                        report: run_failed(
+                            &format!("{}/{}", boards[i], image.name),
+                            start,
+                            anyhow::anyhow!("{e:#}"),
+                        ),
+                    })
+                    .collect();
+            }
+        }
+    }
+
+    Ok(cells.into_iter().flatten().collect())
+}
+
+fn html(farm: &Farm, cells: &[Cell], boards: &[String], images: &[MatrixImage]) -> String {
+    let passed = cells
+        .iter()
+        .filter(|c| c.report.outcome.is_success())
+        .count();
+
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>sk8brd matrix</title>\n\
+         <style>\n\
+         body { font-family: sans-serif; margin: 2em; }\n\
+         table { border-collapse: collapse; }\n\
+         th, td { border: 1px solid #999; padding: 0.4em 0.8em; text-align: center; }\n\
+         td.pass { background: #c8f0c8; }\n\
+         td.fail { background: #f4c2c2; }\n\
+         td.error { background: #f6dfa4; }\n\
+         td a { color: inherit; font-weight: bold; }\n\
+         .time { font-size: 0.85em; color: #444; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+
+    let _ = writeln!(
+        html,
+        "<h1>sk8brd matrix</h1>\n<p>Farm {}: {} runs, {passed} passed, {} failed</p>",
+        xml_escape(&farm.host),
+        cells.len(),
+        cells.len() - passed
+    );
+
+    html.push_str("<table>\n<tr><th>Board</th>");
+    for image in images {
+        let _ = write!(html, "<th>{}</th>", xml_escape(&image.name));
+    }
+    html.push_str("</tr>\n");
+
+    for board in boards {
+        let _ = write!(html, "<tr><th>{}</th>", xml_escape(board));
+        for j in 0..images.len() {
+            let Some(cell) = cells
+                .iter()
+                .find(|c| &c.board == board && c.image_index == j)
+            else {
+                html.push_str("<td>-</td>");
+                continue;
+            };
+
+            let r = &cell.report;
+            let (class, result) = match (r.outcome, &r.failure) {
+                (outcome, _) if outcome.is_success() => ("pass", "PASS".to_string()),
+                (_, Some(f)) if f.is_error() => ("error", "ERROR".to_string()),
+                (Outcome::Error, _) => ("error", "ERROR".to_string()),
+                (outcome, _) => ("fail", format!("{outcome:?}").to_uppercase()),
+            };
+            let reason = r
+                .failure
+                .as_ref()
+                .map(|f| format!("{}: {}", f.kind(), f.message()))
+                .unwrap_or_default();
+            let time = match r.boot_time {
+                Some(t) => format!("boot {:.1} s", t.as_secs_f64()),
+                None => format!("ran {:.1} s", r.duration.as_secs_f64()),
+            };
+
+            let _ = write!(
+                html,
+                "<td class=\"{class}\" title=\"{}\"><a href=\"{}\">{result}</a><br><span class=\"time\">{time}</span></td>",
+                xml_escape(&reason),
+                xml_escape(&cell.log_name())
+            );
+        }
+        html.push_str("</tr>\n");
+    }
+
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// Write the HTML report, along with the console log of each run it links to
+pub fn write_report(
+    dir: &Path,
+    farm: &Farm,
+    cells: &[Cell],
+    boards: &[String],
+    images: &[MatrixImage],
+) -> anyhow::Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Couldn't create {}", dir.display()))?;
+
+    for cell in cells {
+        let path = dir.join(cell.log_name());
+        fs::write(&path, &cell.report.console)
+            .with_context(|| format!("Couldn't write {}", path.display()))?;
+    }
+
+    let path = dir.join("index.html");
+    fs::write(&path, html(farm, cells, boards, images))
+        .with_context(|| format!("Couldn't write {}", path.display()))?;
+    eprintln!("Matrix report written to {}", path.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn cell(board: &str, image: &str, image_index: usize) -> Cell {
+        Cell {
+            board: board.to_string(),
+            image: image.to_string(),
+            image_index,
+            report: RunReport {
+                board: board.to_string(),
+                outcome: Outcome::Pass,
+                failure: None,
+                duration: Duration::from_secs(1),
+                boot_time: None,
+                booted: true,
+                matched: None,
+                milestones: vec![],
+                missed_milestones: vec![],
+                tests: vec![],
+                console: String::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn log_names_are_unique_and_safe() {
+        let images: Vec<MatrixImage> = ["a/boot.img", "b/boot.img", "x#1?<b>=c.img"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let cells: Vec<_> = images
+            .iter()
+            .enumerate()
+            .map(|(j, i)| cell("db845c", &i.name, j))
+            .collect();
+
+        let names: Vec<_> = cells.iter().map(Cell::log_name).collect();
+        assert_eq!(
+            names,
+            [
+                "db845c-1-boot.log",
+                "db845c-2-boot.log",
+                "db845c-3-x_1__b_.log"
+            ]
+        );
+
+        let farm = Farm {
+            host: "farm".to_string(),
+            port: "22".to_string(),
+            user: "cdba".to_string(),
+            jump: None,
+            server: "cdba-server".to_string(),
+        };
+        let html = html(&farm, &cells, &["db845c".to_string()], &images);
+        for name in &names {
+            assert!(html.contains(&format!("href=\"{name}\"")), "{name}");
+        }
+        assert!(html.contains("<th>x#1?&lt;b&gt;</th>"));
+    }
+}
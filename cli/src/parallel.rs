@@ -0,0 +1,147 @@
+use crate::boot::{boot_once, run_failed, BootConfig};
+use crate::report::RunReport;
+use crate::session::{Farm, Session};
+use crate::soak::{self, RepeatArgs};
+use anyhow::bail;
+use regex::Regex;
+use std::collections::HashMap;
+use std::time::Instant;
+use tokio::task::JoinSet;
+
+/// Whether a board argument is a glob pattern rather than a name
+fn is_pattern(s: &str) -> bool {
+    s.contains(['*', '?'])
+}
+
+fn glob(pattern: &str) -> Regex {
+    let re = pattern
+        .split_inclusive(['*', '?'])
+        .map(|part| match part.strip_suffix('*') {
+            Some(lit) => format!("{}.*", regex::escape(lit)),
+            None => match part.strip_suffix('?') {
+                Some(lit) => format!("{}.", regex::escape(lit)),
+                None => regex::escape(part),
+            },
+        })
+        .collect::<String>();
+
+    Regex::new(&format!("^{re}$")).unwrap()
+}
+
+/// Turn the board arguments into board names, matching any patterns against the farm's boards
+pub async fn expand_boards(farm: &Farm, args: &[String]) -> anyhow::Result<Vec<String>> {
+    if !args.iter().any(|a| is_pattern(a)) {
+        return Ok(args.to_vec());
+    }
+
+    let available = Session::connect(farm).await?.list_boards().await?;
+
+    let mut boards: Vec<String> = vec![];
+    for arg in args {
+        let matched = if is_pattern(arg) {
+            let re = glob(arg);
+            available
+                .boards()
+                .iter()
+                .filter(|b| re.is_match(&b.name))
+                .map(|b| b.name.clone())
+                .collect()
+        } else {
+            vec![arg.clone()]
+        };
+
+        if matched.is_empty() {
+            bail!("No board matches `{arg}`");
+        }
+        for board in matched {
+            if !boards.contains(&board) {
+                boards.push(board);
+            }
+        }
+    }
+
+    Ok(boards)
+}
+
+/// Boot each of the boards at the same time, over connections of their own, returning the
+/// reports of each board in the order given
+pub async fn run(
+    farm: &Farm,
+    config: &BootConfig,
+    boards: &[String],
+    repeat: &RepeatArgs,
+) -> Vec<(String, Vec<RunReport>)> {
+    let mut tasks = JoinSet::new();
+    let mut ids = HashMap::new();
+
+    for (i, board) in boards.iter().enumerate() {
+        let mut config = config.with_suffix(board);
+        config.board = board.clone();
+        config.label = Some(board.clone());
+
+        let farm = farm.clone();
+        let repeat = repeat.clone();
+        let start = Instant::now();
+        let task = tasks.spawn(async move {
+            let label = config.board.clone();
+            let reports = if repeat.is_active() {
+                soak::run(&farm, config, &repeat).await
+            } else {
+                boot_once(&farm, config).await.map(|r| vec![r])
+            };
+            reports.unwrap_or_else(|e| vec![run_failed(&label, start, e)])
+        });
+        ids.insert(task.id(), (i, start));
+    }
+
+    // One board breaking off mustn't take the others down along with it
+    let mut runs: Vec<_> = boards.iter().map(|b| (b.clone(), vec![])).collect();
+    while let Some(joined) = tasks.join_next_with_id().await {
+        match joined {
+            Ok((id, reports)) => runs[ids[&id].0].1 = reports,
+            Err(e) => {
+                let (i, start) = ids[&e.id()];
+                runs[i].1 = vec![run_failed(&boards[i], start, e.into())];
+            }
+        }
+    }
+
+    runs
+}
+
+pub fn print_summary(runs: &[(String, Vec<RunReport>)]) {
+    let width = runs
+        .iter()
+        .map(|(b, _)| b.len())
+        .chain([5])
+        .max()
+        .unwrap_or_default();
+
+    println!(
+        "\n{:<width$}  {:>5}  {:>6}  Result",
+        "Board", "Runs", "Passed"
+    );
+    for (board, reports) in runs {
+        let passed = reports.iter().filter(|r| r.outcome.is_success()).count();
+        let result = match reports.iter().find_map(|r| r.failure.as_ref()) {
+            Some(f) => format!("FAIL ({}: {})", f.kind(), f.message()),
+            None if passed == reports.len() => "PASS".to_string(),
+            None => "FAIL".to_string(),
+        };
+
+        println!(
+            "{board:<width$}  {:>5}  {passed:>6}  {result}",
+            reports.len()
+        );
+    }
+
+    let failed = runs
+        .iter()
+        .filter(|(_, reports)| reports.iter().any(|r| !r.outcome.is_success()))
+        .count();
+    println!(
+        "\n{} boards, {} passed, {failed} failed",
+        runs.len(),
+        runs.len() - failed
+    );
+}
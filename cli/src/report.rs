@@ -0,0 +1,390 @@
+use crate::boot::Outcome;
+use crate::results::{TestCase, TestResult};
+use crate::timing::Milestone;
+use anyhow::Context;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Why a run didn't pass
+#[derive(Clone, Debug)]
+pub enum Failure {
+    Timeout(String),
+    FailPattern(String),
+    Script(String),
+    Tests(String),
+    Command(u8),
+    Upload(String),
+    Remote(String),
+}
+
+impl Failure {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Failure::Timeout(_) => "timeout",
+            Failure::FailPattern(_) => "fail-pattern",
+            Failure::Script(_) => "script",
+            Failure::Tests(_) => "tests",
+            Failure::Command(_) => "command",
+            Failure::Upload(_) => "upload-error",
+            Failure::Remote(_) => "remote-error",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Failure::Command(status) => format!("command exited with status {status}"),
+            Failure::Timeout(s)
+            | Failure::FailPattern(s)
+            | Failure::Script(s)
+            | Failure::Tests(s)
+            | Failure::Upload(s)
+            | Failure::Remote(s) => s.clone(),
+        }
+    }
+
+    /// Whether the infrastructure, rather than what ran on the board, is to blame
+    pub fn is_error(&self) -> bool {
+        matches!(self, Failure::Upload(_) | Failure::Remote(_))
+    }
+}
+
+/// Everything worth knowing about a finished run
+#[derive(Clone, Debug)]
+pub struct RunReport {
+    pub board: String,
+    pub outcome: Outcome,
+    pub failure: Option<Failure>,
+    pub duration: Duration,
+    /// From power on to the pass or until pattern matching
+    pub boot_time: Option<Duration>,
+    /// Whether the image was sent (or fastboot told to continue)
+    pub booted: bool,
+    /// The console line that matched a pass or until pattern
+    pub matched: Option<String>,
+    pub milestones: Vec<Milestone>,
+    pub missed_milestones: Vec<String>,
+    pub tests: Vec<TestCase>,
+    pub console: String,
+}
+
+/// A single entry of a report, as both formats see it
+struct Case {
+    name: String,
+    time: Option<f64>,
+    result: CaseResult,
+    /// A value measured by the test, with its units
+    measurement: Option<String>,
+}
+
+enum CaseResult {
+    Pass,
+    Skip(String),
+    Todo(String),
+    Fail(String, String),
+    Error(String, String),
+}
+
+fn cases(report: &RunReport) -> Vec<Case> {
+    let mut cases = vec![Case {
+        name: "boot".to_string(),
+        time: Some(report.duration.as_secs_f64()),
+        result: match &report.failure {
+            Some(f) if f.is_error() => CaseResult::Error(f.kind().to_string(), f.message()),
+            Some(f) => CaseResult::Fail(f.kind().to_string(), f.message()),
+            None => CaseResult::Pass,
+        },
+        measurement: None,
+    }];
+
+    if let Some(line) = &report.matched {
+        cases.push(Case {
+            name: format!("pattern: {line}"),
+            time: None,
+            result: CaseResult::Pass,
+            measurement: None,
+        });
+    }
+
+    for m in &report.milestones {
+        cases.push(Case {
+            name: format!("milestone: {}", m.name),
+            time: Some(m.time),
+            result: CaseResult::Pass,
+            measurement: None,
+        });
+    }
+
+    for m in &report.missed_milestones {
+        cases.push(Case {
+            name: format!("milestone: {m}"),
+            time: None,
+            result: CaseResult::Skip("not reached".to_string()),
+            measurement: None,
+        });
+    }
+
+    for t in &report.tests {
+        let directive = t.directive.clone().unwrap_or_default();
+        cases.push(Case {
+            name: format!("test: {}", t.name),
+            time: None,
+            result: match t.result {
+                TestResult::Pass => CaseResult::Pass,
+                TestResult::Skip => CaseResult::Skip(directive),
+                TestResult::Todo => CaseResult::Todo(directive),
+                TestResult::Fail => CaseResult::Fail("test".to_string(), directive),
+            },
+            measurement: t.measurement.clone(),
+        });
+    }
+
+    cases
+}
+
+/// Drop the (control) characters XML can't carry at all
+fn xml_chars(s: &str) -> String {
+    s.chars()
+        .filter(|&c| c >= ' ' || matches!(c, '\t' | '\n' | '\r'))
+        .collect()
+}
+
+pub fn xml_escape(s: &str) -> String {
+    xml_chars(s)
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+pub fn junit(reports: &[RunReport]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let all: Vec<_> = reports.iter().map(|r| (r, cases(r))).collect();
+    let count = |f: fn(&CaseResult) -> bool| -> usize {
+        all.iter()
+            .map(|(_, cases)| cases.iter().filter(|c| f(&c.result)).count())
+            .sum()
+    };
+
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"sk8brd\" tests=\"{}\" failures=\"{}\" errors=\"{}\">",
+        count(|_| true),
+        count(|r| matches!(r, CaseResult::Fail(..))),
+        count(|r| matches!(r, CaseResult::Error(..))),
+    );
+
+    for (report, cases) in &all {
+        let board = xml_escape(&report.board);
+        let n = |f: fn(&CaseResult) -> bool| cases.iter().filter(|c| f(&c.result)).count();
+
+        let _ = writeln!(
+            xml,
+            "  <testsuite name=\"{board}\" tests=\"{}\" failures=\"{}\" errors=\"{}\" skipped=\"{}\" time=\"{:.3}\">",
+            cases.len(),
+            n(|r| matches!(r, CaseResult::Fail(..))),
+            n(|r| matches!(r, CaseResult::Error(..))),
+            n(|r| matches!(r, CaseResult::Skip(_) | CaseResult::Todo(_))),
+            report.duration.as_secs_f64(),
+        );
+
+        for case in cases {
+            let time = case
+                .time
+                .map(|t| format!(" time=\"{t:.3}\""))
+                .unwrap_or_default();
+            let _ = write!(
+                xml,
+                "    <testcase classname=\"{board}\" name=\"{}\"{time}",
+                xml_escape(&case.name)
+            );
+
+            let mut children = String::new();
+            if let Some(m) = &case.measurement {
+                let _ = writeln!(
+                    children,
+                    "      <properties>\n        <property name=\"measurement\" value=\"{}\"/>\n      </properties>",
+                    xml_escape(m)
+                );
+            }
+            let _ = match &case.result {
+                CaseResult::Pass => Ok(()),
+                CaseResult::Skip(msg) | CaseResult::Todo(msg) => {
+                    writeln!(children, "      <skipped message=\"{}\"/>", xml_escape(msg))
+                }
+                CaseResult::Fail(kind, msg) => writeln!(
+                    children,
+                    "      <failure type=\"{kind}\" message=\"{}\"/>",
+                    xml_escape(msg)
+                ),
+                CaseResult::Error(kind, msg) => writeln!(
+                    children,
+                    "      <error type=\"{kind}\" message=\"{}\"/>",
+                    xml_escape(msg)
+                ),
+            };
+
+            let _ = if children.is_empty() {
+                writeln!(xml, "/>")
+            } else {
+                writeln!(xml, ">\n{children}    </testcase>")
+            };
+        }
+
+        // CDATA can't contain its own terminator, split it across two sections
+        let console = xml_chars(&report.console).replace("]]>", "]]]]><![CDATA[>");
+        let _ = writeln!(
+            xml,
+            "    <system-out><![CDATA[{console}]]></system-out>\n  </testsuite>"
+        );
+    }
+
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+pub fn tap(reports: &[RunReport]) -> String {
+    let all: Vec<_> = reports
+        .iter()
+        .flat_map(|r| cases(r).into_iter().map(move |c| (r, c)))
+        .collect();
+
+    let mut tap = format!("TAP version 13\n1..{}\n", all.len());
+    for (i, (report, case)) in all.iter().enumerate() {
+        let name = format!("{}: {}", report.board, case.name).replace('#', "\\#");
+        let _ = match &case.result {
+            CaseResult::Pass => writeln!(tap, "ok {} - {name}", i + 1),
+            CaseResult::Skip(msg) => writeln!(tap, "ok {} - {name} # SKIP {msg}", i + 1),
+            CaseResult::Todo(msg) => writeln!(tap, "not ok {} - {name} # TODO {msg}", i + 1),
+            CaseResult::Fail(kind, msg) | CaseResult::Error(kind, msg) => {
+                writeln!(tap, "not ok {} - {name}", i + 1)
+                    .and_then(|_| writeln!(tap, "# {kind}: {msg}"))
+            }
+        };
+        if let Some(m) = &case.measurement {
+            let _ = writeln!(tap, "# measurement: {m}");
+        }
+    }
+
+    tap
+}
+
+pub fn write_reports(
+    junit_path: Option<&Path>,
+    tap_path: Option<&Path>,
+    reports: &[RunReport],
+) -> anyhow::Result<()> {
+    if let Some(path) = junit_path {
+        fs::write(path, junit(reports))
+            .with_context(|| format!("Couldn't write {}", path.display()))?;
+    }
+
+    if let Some(path) = tap_path {
+        fs::write(path, tap(reports))
+            .with_context(|| format!("Couldn't write {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> RunReport {
+        RunReport {
+            board: "db845c".to_string(),
+            outcome: Outcome::Fail,
+            failure: Some(Failure::FailPattern(
+                "matched `<panic> & \"oops\"`".to_string(),
+            )),
+            duration: Duration::from_millis(12_500),
+            boot_time: None,
+            booted: true,
+            matched: None,
+            milestones: vec![Milestone {
+                name: "kernel".to_string(),
+                time: 1.25,
+            }],
+            missed_milestones: vec!["login".to_string()],
+            tests: vec![
+                TestCase {
+                    name: "selftests.net#1".to_string(),
+                    result: TestResult::Pass,
+                    directive: None,
+                    has_subtests: false,
+                    measurement: Some("42 ms".to_string()),
+                },
+                TestCase {
+                    name: "selftests.mm".to_string(),
+                    result: TestResult::Skip,
+                    directive: Some("no hugepages".to_string()),
+                    has_subtests: false,
+                    measurement: None,
+                },
+            ],
+            console: "boot\x1b[0m ok\x07\r\nnested ]]> end\n".to_string(),
+        }
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!(
+            xml_escape("<a href='x'>\"&\"</a>"),
+            "&lt;a href=&apos;x&apos;&gt;&quot;&amp;&quot;&lt;/a&gt;"
+        );
+        assert_eq!(xml_escape("a\x00b\x1bc\td\r\n"), "abc\td\r\n");
+    }
+
+    #[test]
+    fn junit_report() {
+        let xml = junit(&[report()]);
+
+        assert!(
+            xml.contains("<testsuites name=\"sk8brd\" tests=\"5\" failures=\"1\" errors=\"0\">")
+        );
+        assert!(xml.contains(
+            "<testsuite name=\"db845c\" tests=\"5\" failures=\"1\" errors=\"0\" skipped=\"2\" time=\"12.500\">"
+        ));
+        assert!(xml.contains(
+            "<failure type=\"fail-pattern\" message=\"matched `&lt;panic&gt; &amp; &quot;oops&quot;`\"/>"
+        ));
+        assert!(xml.contains("name=\"milestone: kernel\" time=\"1.250\"/>"));
+        assert!(xml.contains("<skipped message=\"not reached\"/>"));
+        assert!(xml.contains("<property name=\"measurement\" value=\"42 ms\"/>"));
+        assert!(xml.contains(
+            "<system-out><![CDATA[boot[0m ok\r\nnested ]]]]><![CDATA[> end\n]]></system-out>"
+        ));
+        assert!(!xml.contains(['\x1b', '\x07']));
+    }
+
+    #[test]
+    fn junit_error() {
+        let mut report = report();
+        report.failure = Some(Failure::Remote("connection reset".to_string()));
+
+        let xml = junit(&[report]);
+        assert!(xml.contains("failures=\"0\" errors=\"1\""));
+        assert!(xml.contains("<error type=\"remote-error\" message=\"connection reset\"/>"));
+    }
+
+    #[test]
+    fn tap_report() {
+        let tap = tap(&[report()]);
+
+        assert_eq!(
+            tap,
+            "TAP version 13\n\
+             1..5\n\
+             not ok 1 - db845c: boot\n\
+             # fail-pattern: matched `<panic> & \"oops\"`\n\
+             ok 2 - db845c: milestone: kernel\n\
+             ok 3 - db845c: milestone: login # SKIP not reached\n\
+             ok 4 - db845c: test: selftests.net\\#1\n\
+             # measurement: 42 ms\n\
+             ok 5 - db845c: test: selftests.mm # SKIP no hugepages\n"
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TestResult {
+    Pass,
+    Fail,
+    Skip,
+    /// Known to be broken, not counted as a failure
+    Todo,
+}
+
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TestResult::Pass => "PASS",
+            TestResult::Fail => "FAIL",
+            TestResult::Skip => "SKIP",
+            TestResult::Todo => "TODO",
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TestCase {
+    /// Names of the enclosing test runs/subtests and the test itself, joined with dots
+    pub name: String,
+    pub result: TestResult,
+    /// The SKIP/TODO reason or any other directive
+    pub directive: Option<String>,
+    /// Whether this is the summary line of a test with subtests of its own
+    pub has_subtests: bool,
+    /// A value the test measured, with its units
+    pub measurement: Option<String>,
+}
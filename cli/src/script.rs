@@ -0,0 +1,434 @@
+use anyhow::{bail, Context};
+use regex::Regex;
+use sk8brd::console::ConsoleTail;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub const DEFAULT_EXPECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How many console lines are shown around a failed step
+const CONTEXT_LINES: usize = 20;
+
+/// Don't let the unmatched output grow without bounds while waiting for a pattern
+const MAX_PENDING_OUTPUT: usize = 64 * 1024;
+
+#[derive(Clone, Debug)]
+pub enum Step {
+    Send(Vec<u8>),
+    Expect(Regex),
+    Timeout(Duration),
+    Sleep(Duration),
+    Break,
+}
+
+#[derive(Clone, Debug)]
+pub struct ScriptStep {
+    /// Line number in the script file
+    pub line: usize,
+    /// The step as written
+    pub text: String,
+    pub step: Step,
+}
+
+impl fmt::Display for ScriptStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: `{}`", self.line, self.text)
+    }
+}
+
+/// Strip a pair of surrounding double quotes, if any
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s)
+}
+
+/// Expand C-style escapes (\n, \r, \t, \e, \\, \xNN) in a send string
+pub fn unescape(s: &str) -> anyhow::Result<Vec<u8>> {
+    let mut out = vec![];
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut utf8 = [0u8; 4];
+            out.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => out.push(b'\n'),
+            Some('r') => out.push(b'\r'),
+            Some('t') => out.push(b'\t'),
+            Some('e') => out.push(0x1b),
+            Some('\\') => out.push(b'\\'),
+            Some('"') => out.push(b'"'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                out.push(
+                    u8::from_str_radix(&hex, 16).with_context(|| format!("bad escape \\x{hex}"))?,
+                );
+            }
+            Some(c) => bail!("unknown escape \\{c}"),
+            None => bail!("trailing backslash"),
+        }
+    }
+
+    Ok(out)
+}
+
+/// A non-negative, finite number of seconds
+pub fn secs(secs: f64) -> anyhow::Result<Duration> {
+    Duration::try_from_secs_f64(secs)
+        .with_context(|| format!("`{secs}` is not a valid number of seconds"))
+}
+
+fn parse_secs(s: &str) -> anyhow::Result<Duration> {
+    let value: f64 = s
+        .parse()
+        .with_context(|| format!("`{s}` is not a number of seconds"))?;
+    secs(value)
+}
+
+/// Parse a single script step, e.g. `expect login:` or `sendline root`
+pub fn parse_step(text: &str) -> anyhow::Result<Step> {
+    let (cmd, arg) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let arg = unquote(arg.trim());
+
+    Ok(match cmd {
+        "send" => Step::Send(unescape(arg)?),
+        "sendline" => Step::Send([unescape(arg)?, b"\n".to_vec()].concat()),
+        "expect" => Step::Expect(Regex::new(arg)?),
+        "timeout" => Step::Timeout(parse_secs(arg)?),
+        "sleep" => Step::Sleep(parse_secs(arg)?),
+        "break" => Step::Break,
+        _ => bail!("unknown command `{cmd}`"),
+    })
+}
+
+/// Parse a script: one step per line, `#` starts a comment line
+pub fn parse(script: &str) -> anyhow::Result<Vec<ScriptStep>> {
+    let mut steps = vec![];
+
+    for (i, text) in script.lines().enumerate() {
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        steps.push(ScriptStep {
+            line: i + 1,
+            text: text.to_string(),
+            step: parse_step(text).with_context(|| format!("line {}", i + 1))?,
+        });
+    }
+
+    Ok(steps)
+}
+
+pub fn load(path: &Path) -> anyhow::Result<Vec<ScriptStep>> {
+    let script =
+        fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    parse(&script).with_context(|| format!("Invalid script {}", path.display()))
+}
+
+/// What the caller has to do on behalf of the script
+#[derive(Debug)]
+pub enum Action {
+    Send(Vec<u8>),
+    Break,
+}
+
+/// A failed script step, with the console output that led up to it
+#[derive(Debug)]
+pub struct ScriptError {
+    pub step: String,
+    pub reason: String,
+    pub context: Vec<String>,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Script failed at {}: {}", self.step, self.reason)?;
+        writeln!(f, "Console context:")?;
+        for line in &self.context {
+            writeln!(f, "  | {line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Steps through a script as console output comes in
+pub struct ScriptRunner {
+    steps: Vec<ScriptStep>,
+    pos: usize,
+    started: bool,
+    expect_timeout: Duration,
+    /// When the current expect or sleep step runs out
+    deadline: Option<Instant>,
+    /// Console output not consumed by an expect yet
+    pending: ConsoleTail,
+    context: VecDeque<String>,
+    partial_line: String,
+}
+
+impl ScriptRunner {
+    pub fn new(steps: Vec<ScriptStep>) -> Self {
+        Self {
+            steps,
+            pos: 0,
+            started: false,
+            expect_timeout: DEFAULT_EXPECT_TIMEOUT,
+            deadline: None,
+            pending: ConsoleTail::new(MAX_PENDING_OUTPUT),
+            context: VecDeque::new(),
+            partial_line: String::new(),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pos >= self.steps.len()
+    }
+
+    pub fn start(&mut self) {
+        self.started = true;
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// The script failing at the current step, unless it already ran to its end
+    pub fn unfinished(&self, reason: &str) -> Option<ScriptError> {
+        (!self.is_done()).then(|| self.fail(reason))
+    }
+
+    fn fail(&self, reason: &str) -> ScriptError {
+        let mut context: Vec<_> = self.context.iter().cloned().collect();
+        if !self.partial_line.is_empty() {
+            context.push(self.partial_line.clone());
+        }
+
+        ScriptError {
+            step: self.steps[self.pos].to_string(),
+            reason: reason.to_string(),
+            context,
+        }
+    }
+
+    /// Record console output, possibly satisfying the current expect step
+    pub fn console(&mut self, buf: &[u8]) {
+        let text = String::from_utf8_lossy(buf).replace('\r', "");
+
+        for c in text.chars() {
+            if c == '\n' {
+                if self.context.len() == CONTEXT_LINES {
+                    self.context.pop_front();
+                }
+                self.context
+                    .push_back(std::mem::take(&mut self.partial_line));
+            } else {
+                self.partial_line.push(c);
+            }
+        }
+
+        if !self.started || self.is_done() {
+            return;
+        }
+
+        self.pending.push(buf);
+        self.pending.trim();
+
+        if let Step::Expect(re) = &self.steps[self.pos].step {
+            if let Some(m) = re.find(self.pending.as_str()) {
+                self.pending.consume(m.end());
+                self.deadline = None;
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// Run the steps that don't need to wait, returning what has to be sent to the board
+    pub fn advance(&mut self) -> Result<Vec<Action>, ScriptError> {
+        let mut actions = vec![];
+
+        if !self.started {
+            return Ok(actions);
+        }
+
+        while !self.is_done() {
+            let now = Instant::now();
+
+            match &self.steps[self.pos].step {
+                Step::Send(buf) => actions.push(Action::Send(buf.clone())),
+                Step::Break => actions.push(Action::Break),
+                Step::Timeout(d) => self.expect_timeout = *d,
+                Step::Sleep(d) => match self.deadline {
+                    None => {
+                        self.deadline = Some(now + *d);
+                        break;
+                    }
+                    Some(deadline) if deadline > now => break,
+                    Some(_) => self.deadline = None,
+                },
+                Step::Expect(re) => match self.deadline {
+                    None => {
+                        // The output of the previous steps may already hold the match
+                        match re.find(self.pending.as_str()) {
+                            Some(m) => self.pending.consume(m.end()),
+                            None => {
+                                self.deadline = Some(now + self.expect_timeout);
+                                break;
+                            }
+                        }
+                    }
+                    Some(deadline) if deadline > now => break,
+                    Some(_) => {
+                        return Err(self.fail(&format!(
+                            "no match within {}s",
+                            self.expect_timeout.as_secs_f64()
+                        )))
+                    }
+                },
+            }
+
+            self.pos += 1;
+        }
+
+        Ok(actions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runner(script: &str) -> ScriptRunner {
+        let mut runner = ScriptRunner::new(parse(script).unwrap());
+        runner.start();
+        runner
+    }
+
+    fn sent(actions: &[Action]) -> Vec<u8> {
+        actions
+            .iter()
+            .flat_map(|a| match a {
+                Action::Send(buf) => buf.clone(),
+                Action::Break => vec![],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn steps() {
+        assert!(matches!(parse_step("send ls\\r").unwrap(), Step::Send(b) if b == b"ls\r"));
+        assert!(
+            matches!(parse_step("sendline \"root \"").unwrap(), Step::Send(b) if b == b"root \n")
+        );
+        assert!(
+            matches!(parse_step("expect login:").unwrap(), Step::Expect(re) if re.as_str() == "login:")
+        );
+        assert!(
+            matches!(parse_step("timeout 2.5").unwrap(), Step::Timeout(d) if d == Duration::from_millis(2500))
+        );
+        assert!(
+            matches!(parse_step("sleep 1").unwrap(), Step::Sleep(d) if d == Duration::from_secs(1))
+        );
+        assert!(matches!(parse_step("break").unwrap(), Step::Break));
+        assert!(parse_step("expect (").is_err());
+        assert!(parse_step("wait 1").is_err());
+    }
+
+    #[test]
+    fn bad_durations() {
+        for text in [
+            "timeout -1",
+            "sleep -0.5",
+            "sleep inf",
+            "timeout NaN",
+            "sleep x",
+        ] {
+            assert!(parse_step(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            unescape(r#"a\n\r\t\e\\\"\x41"#).unwrap(),
+            b"a\n\r\t\x1b\\\"A"
+        );
+        assert_eq!(unescape("ü").unwrap(), "ü".as_bytes());
+        assert!(unescape(r"\xZZ").is_err());
+        assert!(unescape(r"\q").is_err());
+        assert!(unescape("a\\").is_err());
+    }
+
+    #[test]
+    fn line_numbers() {
+        let steps = parse("# log in\n\nexpect login:\n  sendline root\n").unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[1].to_string(), "line 4: `sendline root`");
+
+        let e = parse("break\nfoo\n").unwrap_err();
+        assert_eq!(format!("{e:#}"), "line 2: unknown command `foo`");
+    }
+
+    #[test]
+    fn waits_until_started() {
+        let mut runner = ScriptRunner::new(parse("sendline root").unwrap());
+        assert!(runner.advance().unwrap().is_empty());
+
+        runner.start();
+        assert_eq!(sent(&runner.advance().unwrap()), b"root\n");
+        assert!(runner.is_done());
+        assert!(runner.unfinished("timed out").is_none());
+    }
+
+    #[test]
+    fn expect() {
+        let mut runner = runner("expect login:\nsendline root\nexpect Password:");
+        assert!(runner.advance().unwrap().is_empty());
+        assert!(runner.deadline().is_some());
+
+        runner.console(b"Welcome\r\nlog");
+        assert!(runner.advance().unwrap().is_empty());
+        runner.console(b"in: ");
+        assert_eq!(sent(&runner.advance().unwrap()), b"root\n");
+        assert!(!runner.is_done());
+    }
+
+    #[test]
+    fn expect_earlier_output() {
+        // The prompt shows up while the command is being sent, before the expect step is reached
+        let mut runner = runner("sendline uname\nexpect Linux\nexpect #");
+        runner.console(b"uname\nLinux\n# ");
+        assert_eq!(sent(&runner.advance().unwrap()), b"uname\n");
+        assert!(runner.is_done());
+    }
+
+    #[test]
+    fn expect_timeout() {
+        let mut runner = runner("timeout 0\nexpect never");
+        runner.console(b"booting\nalmost");
+        assert!(runner.advance().unwrap().is_empty());
+
+        let e = runner.advance().unwrap_err();
+        assert_eq!(e.step, "line 2: `expect never`");
+        assert_eq!(e.reason, "no match within 0s");
+        assert_eq!(e.context, ["booting", "almost"]);
+    }
+
+    #[test]
+    fn unfinished() {
+        let mut runner = runner("sendline root\nexpect #");
+        runner.advance().unwrap();
+
+        let e = runner.unfinished("timed out").unwrap();
+        assert_eq!(e.step, "line 2: `expect #`");
+        assert_eq!(e.reason, "timed out");
+    }
+}
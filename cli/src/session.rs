@@ -0,0 +1,125 @@
+use anyhow::{bail, Context};
+use russh::client::Msg;
+use sk8brd::board::{BoardInfo, BoardList};
+use sk8brd::ssh::{ssh_connect_via, SSH_BUFFER_SIZE};
+use sk8brd::Sk8brdMsg;
+use sk8brd::{recv_msg, request_board_info, select_brd, send_ack, Sk8brdMsgs};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, Mutex};
+
+pub use sk8brd::config::Farm;
+
+pub type ServerSink = Arc<Mutex<Box<dyn AsyncWrite + Send + Unpin>>>;
+
+/// A connection to the server, with both of its output streams read in the background so
+/// that nothing waiting on them ever blocks
+pub struct Session {
+    pub stdin: ServerSink,
+    pub msgs: mpsc::Receiver<(Sk8brdMsg, Vec<u8>)>,
+    pub stderr: mpsc::Receiver<Vec<u8>>,
+}
+
+impl Session {
+    pub async fn connect(farm: &Farm) -> anyhow::Result<Self> {
+        let chan = Arc::new(Mutex::new(
+            ssh_connect_via(&farm.address(), farm.user.clone(), farm.jump.as_deref()).await?,
+        ));
+        (*chan.lock().await)
+            .exec(true, farm.server.as_str())
+            .await
+            .with_context(|| format!("Couldn't execute {} on remote server", farm.server))?;
+
+        let stdin: ServerSink = Arc::new(Mutex::new(Box::new((*chan.lock().await).make_writer())));
+        let (mut server_stdout, mut server_stderr) = sk8brd::ssh::into_streams::<Msg>(chan).await;
+
+        let (msg_tx, msgs) = mpsc::channel(100);
+        tokio::spawn(async move {
+            while let Ok(msg) = recv_msg(&mut server_stdout).await {
+                if msg_tx.send(msg).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (stderr_tx, stderr) = mpsc::channel(100);
+        tokio::spawn(async move {
+            let mut buf = [0u8; SSH_BUFFER_SIZE];
+            while let Ok(bytes_read @ 1..) = server_stderr.read(&mut buf).await {
+                if stderr_tx.send(buf[..bytes_read].to_vec()).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            stdin,
+            msgs,
+            stderr,
+        })
+    }
+
+    /// Ask the server for its boards
+    pub async fn list_boards(&mut self) -> anyhow::Result<BoardList> {
+        send_ack(&mut self.stdin, Sk8brdMsgs::MsgListDevices).await?;
+
+        let mut list = BoardList::new();
+        loop {
+            let Some((msg, buf)) = self.msgs.recv().await else {
+                bail!("Connection to the server was lost");
+            };
+
+            if matches!(msg.r#type.try_into(), Ok(Sk8brdMsgs::MsgListDevices)) && list.push(&buf) {
+                return Ok(list);
+            }
+        }
+    }
+
+    /// Ask the server about a board
+    pub async fn board_info(&mut self, board: &str) -> anyhow::Result<BoardInfo> {
+        request_board_info(&mut self.stdin, board).await?;
+
+        loop {
+            let Some((msg, buf)) = self.msgs.recv().await else {
+                bail!("Connection to the server was lost");
+            };
+
+            if matches!(msg.r#type.try_into(), Ok(Sk8brdMsgs::MsgBoardInfo)) {
+                return Ok(BoardInfo::parse(board, &buf));
+            }
+        }
+    }
+
+    /// Select the board, waiting for the server to confirm
+    pub async fn select_board(&mut self, board: &str) -> anyhow::Result<()> {
+        select_brd(&mut self.stdin, board).await?;
+        self.wait_for(Sk8brdMsgs::MsgSelectBoard).await
+    }
+
+    /// Wait for a message of the given type, skipping all others
+    pub async fn wait_for(&mut self, r#type: Sk8brdMsgs) -> anyhow::Result<()> {
+        loop {
+            let Some((msg, _)) = self.msgs.recv().await else {
+                bail!("Connection to the server was lost");
+            };
+
+            if msg.r#type == r#type as u8 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Send a message the server replies to in kind, and wait for the reply
+    pub async fn request(&mut self, r#type: Sk8brdMsgs) -> anyhow::Result<()> {
+        send_ack(&mut self.stdin, r#type).await?;
+        self.wait_for(r#type).await
+    }
+
+    /// Hang up, giving the server the chance to act on everything sent so far
+    pub async fn disconnect(mut self) -> anyhow::Result<()> {
+        self.stdin.lock().await.shutdown().await?;
+        while self.msgs.recv().await.is_some() {}
+
+        Ok(())
+    }
+}
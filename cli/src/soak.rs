@@ -0,0 +1,119 @@
+use crate::boot::{boot_once, BootConfig};
+use crate::report::RunReport;
+use crate::session::Farm;
+use colored::Colorize;
+use sk8brd::power::StartPolicy;
+use std::time::Duration;
+
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct RepeatArgs {
+    /// Boot the board this many times, power cycling it in between
+    #[arg(long)]
+    pub repeat: Option<u32>,
+
+    /// Keep booting until a run doesn't pass (at most --repeat times, if given)
+    #[arg(long, default_value_t = false)]
+    pub until_fail: bool,
+}
+
+impl RepeatArgs {
+    pub fn is_active(&self) -> bool {
+        self.repeat.is_some() || self.until_fail
+    }
+}
+
+/// Each iteration keeps its own files, e.g. `console.log` -> `console-3.log`
+pub fn iteration_config(config: &BootConfig, i: u32) -> BootConfig {
+    let mut config = config.with_suffix(&i.to_string());
+
+    // Each boot starts from a board that was just powered off
+    config.start_power = StartPolicy::Cycle;
+    config
+}
+
+/// Boot the board over and over, each time over a fresh connection
+pub async fn run(
+    farm: &Farm,
+    config: BootConfig,
+    args: &RepeatArgs,
+) -> anyhow::Result<Vec<RunReport>> {
+    let mut reports = vec![];
+    let total = args
+        .repeat
+        .map(|n| n.to_string())
+        .unwrap_or_else(|| "?".to_string());
+
+    for i in 1.. {
+        if args.repeat.is_some_and(|n| i > n) {
+            break;
+        }
+
+        let label = config
+            .label
+            .as_deref()
+            .map(|l| format!("[{l}] "))
+            .unwrap_or_default();
+        eprintln!(
+            "{}",
+            format!("\n{label}=== Iteration {i}/{total} ===").bold()
+        );
+
+        let mut report = boot_once(farm, iteration_config(&config, i)).await?;
+        report.board = format!("{}#{i}", report.board);
+
+        let passed = report.outcome.is_success();
+        reports.push(report);
+
+        if args.until_fail && !passed {
+            break;
+        }
+    }
+
+    Ok(reports)
+}
+
+/// The p-th percentile of sorted samples, by the nearest rank
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    let rank = (p * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+pub fn print_summary(reports: &[RunReport]) {
+    let passed = reports.iter().filter(|r| r.outcome.is_success()).count();
+
+    println!(
+        "\nIterations: {}, passed: {passed}, success rate: {:.1}%",
+        reports.len(),
+        100.0 * passed as f64 / reports.len().max(1) as f64
+    );
+
+    let mut boot_times: Vec<_> = reports
+        .iter()
+        .filter(|r| r.outcome.is_success())
+        .filter_map(|r| r.boot_time)
+        .collect();
+    boot_times.sort();
+    if !boot_times.is_empty() {
+        println!(
+            "Boot time (s): min {:.3}, p50 {:.3}, p90 {:.3}, p99 {:.3}, max {:.3}",
+            boot_times[0].as_secs_f64(),
+            percentile(&boot_times, 50).as_secs_f64(),
+            percentile(&boot_times, 90).as_secs_f64(),
+            percentile(&boot_times, 99).as_secs_f64(),
+            boot_times[boot_times.len() - 1].as_secs_f64(),
+        );
+    }
+
+    if let Some((i, r)) = reports
+        .iter()
+        .enumerate()
+        .find(|(_, r)| !r.outcome.is_success())
+    {
+        let reason = r
+            .failure
+            .as_ref()
+            .map(|f| format!("{}: {}", f.kind(), f.message()))
+            .unwrap_or_else(|| format!("{:?}", r.outcome));
+        println!("First failing iteration: {} ({reason})", i + 1);
+    }
+}
@@ -0,0 +1,89 @@
+use std::fmt;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Which of the deadlines ran out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timeout {
+    Total(Duration),
+    Inactivity(Duration),
+    Fastboot(Duration),
+}
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Timeout::Total(d) => write!(f, "total deadline of {}s exceeded", d.as_secs()),
+            Timeout::Inactivity(d) => write!(f, "no console output for {}s", d.as_secs()),
+            Timeout::Fastboot(d) => write!(f, "fastboot didn't show up within {}s", d.as_secs()),
+        }
+    }
+}
+
+/// Tracks the total, inactivity and fastboot deadlines of a run
+pub struct Timers {
+    total: Duration,
+    inactivity: Option<Duration>,
+    fastboot: Option<Duration>,
+
+    start: Instant,
+    /// Last console byte (or other sign of life), armed once the board is powered on
+    last_activity: Option<Instant>,
+    /// Set while waiting for fastboot to show up after powering on
+    fastboot_wait: Option<Instant>,
+}
+
+impl Timers {
+    pub fn new(total: Duration, inactivity: Option<Duration>, fastboot: Option<Duration>) -> Self {
+        Self {
+            total,
+            inactivity,
+            fastboot,
+            start: Instant::now(),
+            last_activity: None,
+            fastboot_wait: None,
+        }
+    }
+
+    /// Restart all the clocks, the board has just been powered on
+    pub fn power_on(&mut self) {
+        let now = Instant::now();
+
+        self.start = now;
+        self.last_activity = Some(now);
+        self.fastboot_wait = Some(now);
+    }
+
+    /// Console output (or a long operation like the upload) happened
+    pub fn activity(&mut self) {
+        if self.last_activity.is_some() {
+            self.last_activity = Some(Instant::now());
+        }
+    }
+
+    pub fn fastboot_present(&mut self) {
+        self.fastboot_wait = None;
+    }
+
+    /// The earliest armed deadline and the timeout it corresponds to
+    pub fn next_deadline(&self) -> (Instant, Timeout) {
+        let mut next = (self.start + self.total, Timeout::Total(self.total));
+
+        let candidates = [
+            self.inactivity
+                .zip(self.last_activity)
+                .map(|(d, since)| (since + d, Timeout::Inactivity(d))),
+            self.fastboot
+                .zip(self.fastboot_wait)
+                .map(|(d, since)| (since + d, Timeout::Fastboot(d))),
+        ];
+
+        for candidate in candidates.into_iter().flatten() {
+            if candidate.0 < next.0 {
+                next = candidate;
+            }
+        }
+
+        next
+    }
+}
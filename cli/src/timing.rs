@@ -0,0 +1,115 @@
+use regex::Regex;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Milestone {
+    pub name: String,
+    /// Seconds since the start of the run
+    pub time: f64,
+}
+
+/// Records when the interesting points of a boot were reached
+pub struct BootTiming {
+    start: Instant,
+    milestones: Vec<Milestone>,
+    /// User-defined console milestones that haven't been seen yet
+    pending: Vec<Regex>,
+    /// Whether the next console output is the first one after the boot was kicked off
+    await_console: bool,
+}
+
+impl BootTiming {
+    pub fn new(patterns: Vec<Regex>) -> Self {
+        Self {
+            start: Instant::now(),
+            milestones: vec![],
+            pending: patterns,
+            await_console: false,
+        }
+    }
+
+    pub fn mark(&mut self, name: &str) {
+        self.milestones.push(Milestone {
+            name: name.to_string(),
+            time: self.start.elapsed().as_secs_f64(),
+        });
+    }
+
+    /// The image is on its way (or fastboot was told to continue), watch for the first signs of life
+    pub fn boot_started(&mut self) {
+        self.await_console = true;
+    }
+
+    /// Note any console output
+    pub fn console(&mut self) {
+        if self.await_console {
+            self.await_console = false;
+            self.mark("first console output");
+        }
+    }
+
+    /// Check a console line against the user-defined milestones, each of which is recorded once
+    pub fn console_line(&mut self, line: &str) {
+        let (hit, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition::<Vec<_>, _>(|re| re.is_match(line));
+
+        self.pending = pending;
+        for re in hit {
+            self.mark(re.as_str());
+        }
+    }
+
+    pub fn milestones(&self) -> &[Milestone] {
+        &self.milestones
+    }
+
+    /// The user-defined milestones that were never reached
+    pub fn missed(&self) -> Vec<String> {
+        self.pending.iter().map(|re| re.to_string()).collect()
+    }
+
+    pub fn print_table(&self) {
+        let width = self
+            .milestones
+            .iter()
+            .map(|m| m.name.len())
+            .chain(self.pending.iter().map(|re| re.as_str().len()))
+            .chain([9])
+            .max()
+            .unwrap_or_default();
+
+        println!(
+            "\n{:<width$}  {:>10}  {:>10}",
+            "Milestone", "Time (s)", "Delta (s)"
+        );
+        let mut last = 0.0;
+        for m in &self.milestones {
+            println!(
+                "{:<width$}  {:>10.3}  {:>10.3}",
+                m.name,
+                m.time,
+                m.time - last
+            );
+            last = m.time;
+        }
+
+        for re in &self.pending {
+            println!("{:<width$}  {:>10}  {:>10}", re.as_str(), "-", "-");
+        }
+    }
+
+    pub fn write_json(&self, path: &Path) -> anyhow::Result<()> {
+        let missed: Vec<_> = self.pending.iter().map(|re| re.as_str()).collect();
+        let json = serde_json::json!({
+            "milestones": self.milestones,
+            "missed": missed,
+        });
+
+        fs::write(path, serde_json::to_string_pretty(&json)?)?;
+        Ok(())
+    }
+}
@@ -1,18 +1,31 @@
 use anyhow::Context;
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use colored::Colorize;
 use russh::client::Msg;
-use sk8brd::ssh::{ssh_connect, SSH_BUFFER_SIZE};
+use sk8brd::board::{BoardInfo, BoardList};
+use sk8brd::bootimg;
+use sk8brd::config::{Config, FarmArgs};
+use sk8brd::fifo::StatusFifo;
+use sk8brd::log::{ConsoleLog, LogArgs, LogEvent};
+use sk8brd::login::{AutoLogin, LoginArgs};
+use sk8brd::power::{PowerArgs, PowerLog, PowerPolicyArgs, StartPolicy};
+use sk8brd::ssh::{ssh_connect_via, SSH_BUFFER_SIZE};
+use sk8brd::status::StatusUpdate;
 use sk8brd::{
-    console_print, parse_recv_msg, print_string_msg, select_brd, send_ack, send_break,
-    send_console, send_image, send_msg, todo, Sk8brdMsgs, CDBA_SERVER_BIN_NAME, MSG_HDR_SIZE,
+    console_print, parse_recv_msg, request_board_info, request_status_update, select_brd, send_ack,
+    send_break, send_console, send_fastboot_continue, send_fastboot_reboot, send_image, send_msg,
+    todo, Sk8brdMsgs, MSG_HDR_SIZE,
 };
+use sparkline::Sparklines;
 use std::fs;
 use std::io::{stdout, Read, Write};
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWrite};
 use tokio::sync::Mutex;
 
+mod sparkline;
+
 macro_rules! get_arc {
     ($a: expr) => {{
         $a.lock().await
@@ -22,23 +35,49 @@ macro_rules! get_arc {
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    #[arg(short)]
-    farm: String,
-
-    #[arg(short, default_value_t = String::from("22"))]
-    port: String,
+    #[command(flatten)]
+    farm: FarmArgs,
 
-    #[arg(short)]
+    /// Board to use, or an alias from the config file
+    #[arg(short, env = "SK8BRD_BOARD")]
     board: String,
 
-    #[arg(short)]
-    image_path: String,
+    /// Boot image to send; without one, fastboot is told to continue
+    #[arg(short, env = "SK8BRD_IMAGE")]
+    image_path: Option<PathBuf>,
+
+    /// Replace the kernel command line in the boot image's header
+    #[arg(long)]
+    cmdline: Option<String>,
+
+    #[command(flatten)]
+    power_policy: PowerPolicyArgs,
+
+    #[command(flatten)]
+    log: LogArgs,
 
-    #[arg(short, default_value_t = String::from("cdba"))]
-    user: String,
+    #[command(flatten)]
+    login: LoginArgs,
 
+    #[command(flatten)]
+    power: PowerArgs,
+
+    /// Draw sparklines of each channel's current and voltage as status updates come in
     #[arg(long, default_value_t = false)]
-    power_cycle: bool,
+    sparkline: bool,
+}
+
+/// Mark a board event in the console log and, as a milestone, in the power log
+async fn event(
+    log: &Arc<Mutex<ConsoleLog>>,
+    power_log: &mut Option<PowerLog>,
+    event: LogEvent,
+) -> anyhow::Result<()> {
+    get_arc!(log).event(event)?;
+    if let Some(power_log) = power_log {
+        power_log.milestone(&event.to_string())?;
+    }
+    Ok(())
 }
 
 async fn handle_keypress(
@@ -46,12 +85,23 @@ async fn handle_keypress(
     quit: &mut Arc<Mutex<bool>>,
     special: &mut bool,
     message_sink: &mut Arc<Mutex<impl AsyncWrite + Unpin>>,
+    log: &Arc<Mutex<ConsoleLog>>,
+    info: &Arc<Mutex<Option<BoardInfo>>>,
 ) {
     if *special {
         *special = false;
+        let vbus = get_arc!(info).as_ref().is_none_or(BoardInfo::supports_vbus);
+        let status = get_arc!(info)
+            .as_ref()
+            .is_none_or(BoardInfo::supports_status);
+
         match c {
             'a' => send_console(message_sink, &[1u8]).await.unwrap(),
-            'B' => send_break(message_sink).await.unwrap(),
+            'B' => {
+                send_break(message_sink).await.unwrap();
+                get_arc!(log).event(LogEvent::BreakSent).unwrap();
+            }
+            'c' => send_fastboot_continue(message_sink).await.unwrap(),
             'P' => send_ack(message_sink, Sk8brdMsgs::MsgPowerOn)
                 .await
                 .unwrap(),
@@ -59,7 +109,10 @@ async fn handle_keypress(
                 .await
                 .unwrap(),
             'q' => *get_arc!(quit) = true,
-            's' => (), //TODO:
+            'r' => send_fastboot_reboot(message_sink).await.unwrap(),
+            's' if !status => print!("{}\r\n", "This board has no status updates".yellow()),
+            's' => request_status_update(message_sink).await.unwrap(),
+            'V' | 'v' if !vbus => print!("{}\r\n", "This board has no VBUS control".yellow()),
             'V' => send_ack(message_sink, Sk8brdMsgs::MsgVbusOn).await.unwrap(),
             'v' => send_ack(message_sink, Sk8brdMsgs::MsgVbusOff)
                 .await
@@ -83,19 +136,70 @@ async fn main() -> anyhow::Result<()> {
     let mut buf = [0u8; SSH_BUFFER_SIZE];
     let mut key_buf = [0u8; 1];
     let quit = Arc::new(Mutex::new(false));
-    let args = Args::parse();
+    let mut args = Args::parse();
+
+    // The config file fills in whatever the flags and the environment didn't say
+    let config = Config::load(args.farm.config.as_deref())?;
+    let alias = config.alias(&args.board);
+    args.farm.resolve(&config, alias);
+    if let Some(alias) = alias {
+        args.image_path = args.image_path.take().or_else(|| alias.image());
+        args.cmdline = args.cmdline.take().or(alias.cmdline.clone());
+        alias.apply_login(&mut args.login)?;
+    }
+    args.board = config.board_name(&args.board);
+    let Some(farm) = args.farm.farm() else {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "the farm (-f <FARM>) is required",
+            )
+            .exit();
+    };
+
+    let log = Arc::new(Mutex::new(
+        ConsoleLog::new(&args.log).context("Couldn't create the console log")?,
+    ));
 
-    let fastboot_image = fs::read(args.image_path).expect("boot image not found");
+    let mut login = AutoLogin::new(&args.login);
+    let mut board_list = BoardList::new();
+    let info = Arc::new(Mutex::new(None));
+    let mut power_log = args
+        .power
+        .power_log
+        .as_deref()
+        .map(PowerLog::new)
+        .transpose()
+        .context("Couldn't create the power log")?;
+    let mut status_fifo = args
+        .power
+        .status_fifo
+        .as_deref()
+        .map(StatusFifo::new)
+        .transpose()
+        .context("Couldn't set up the status FIFO")?;
+    let mut sparklines = args.sparkline.then(Sparklines::new);
+
+    let fastboot_image = args
+        .image_path
+        .map(|path| fs::read(path).expect("boot image not found"));
+    let fastboot_image = match (fastboot_image, &args.cmdline) {
+        (Some(mut image), Some(cmdline)) => {
+            bootimg::set_cmdline(&mut image, cmdline)?;
+            Some(image)
+        }
+        (image, _) => image,
+    };
 
     println!("sk8brd {}", env!("CARGO_PKG_VERSION"));
 
     let chan = Arc::new(Mutex::new(
-        ssh_connect(&format!("{}:{}", args.farm, args.port), args.user).await?,
+        ssh_connect_via(&farm.address(), farm.user.clone(), farm.jump.as_deref()).await?,
     ));
     get_arc!(chan)
-        .exec(true, CDBA_SERVER_BIN_NAME)
+        .exec(true, farm.server.as_str())
         .await
-        .with_context(|| format!("Couldn't execute {CDBA_SERVER_BIN_NAME} on remote server"))?;
+        .with_context(|| format!("Couldn't execute {} on remote server", farm.server))?;
 
     let mut server_stdin = Arc::new(Mutex::new(get_arc!(chan).make_writer()));
 
@@ -105,15 +209,20 @@ async fn main() -> anyhow::Result<()> {
 
     send_ack(&mut server_stdin, Sk8brdMsgs::MsgListDevices).await?;
     select_brd(&mut server_stdin, &args.board).await?;
-    if args.power_cycle {
+    request_board_info(&mut server_stdin, &args.board).await?;
+    let start_power = args.power_policy.start();
+    if start_power == StartPolicy::Cycle {
         println!("Powering off the board first");
         send_ack(&mut server_stdin, Sk8brdMsgs::MsgPowerOff).await?;
+        event(&log, &mut power_log, LogEvent::PowerOff).await?;
     }
 
     crossterm::terminal::enable_raw_mode()?;
 
     let mut quit2 = Arc::clone(&quit);
     let mut server_stdin2 = Arc::clone(&server_stdin);
+    let log2 = Arc::clone(&log);
+    let info2 = Arc::clone(&info);
     let stdin_handler = tokio::spawn(async move {
         let mut stdin = os_pipe::dup_stdin().expect("Couldn't dup stdin");
         let mut ctrl_a_pressed = false;
@@ -126,6 +235,8 @@ async fn main() -> anyhow::Result<()> {
                         &mut quit2,
                         &mut ctrl_a_pressed,
                         &mut server_stdin2,
+                        &log2,
+                        &info2,
                     )
                     .await;
                 }
@@ -133,69 +244,173 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
-    while !*get_arc!(quit) {
-        // Stream of "blue text" - status updates from the server
-        if let Ok(bytes_read) = (*get_arc!(server_stderr)).read(&mut buf).await {
-            let s = String::from_utf8_lossy(&buf[..bytes_read]);
-            print!(
-                "{}\r",
-                s.split('\n').collect::<Vec<_>>().join("\r\n").blue()
-            );
-            stdout().flush()?;
-        }
+    // Keep the status updates coming for the power log, the status FIFO and the sparklines
+    let status_poller = (args.power.is_active() || sparklines.is_some()).then(|| {
+        let quit = Arc::clone(&quit);
+        let mut server_stdin = Arc::clone(&server_stdin);
+        let info = Arc::clone(&info);
+        let mut interval = tokio::time::interval(args.power.interval());
 
-        // Msg handler
-        // Read the message header first
-        if (*get_arc!(server_stdout))
-            .read_exact(&mut hdr_buf)
-            .await
-            .is_ok()
-        {
-            let msg = parse_recv_msg(&hdr_buf);
-            let mut msgbuf = vec![0u8; msg.len as usize];
-
-            // Now read the actual data...
-            (*get_arc!(server_stdout)).read_exact(&mut msgbuf).await?;
-
-            // ..and process it
-            match msg.r#type.try_into() {
-                Ok(Sk8brdMsgs::MsgSelectBoard) => {
-                    send_msg(&mut server_stdin, Sk8brdMsgs::MsgPowerOn, &[]).await?
+        tokio::spawn(async move {
+            while !*get_arc!(quit) {
+                interval.tick().await;
+                if get_arc!(info)
+                    .as_ref()
+                    .is_none_or(BoardInfo::supports_status)
+                {
+                    let _ = request_status_update(&mut server_stdin).await;
                 }
-                Ok(Sk8brdMsgs::MsgConsole) => console_print(&msgbuf).await,
-                Ok(Sk8brdMsgs::MsgHardReset) => todo!("MsgHardReset is unused"),
-                Ok(Sk8brdMsgs::MsgPowerOn) => (),
-                Ok(Sk8brdMsgs::MsgPowerOff) => (),
-                Ok(Sk8brdMsgs::MsgFastbootPresent) => {
-                    if !msgbuf.is_empty() && msgbuf[0] != 0 {
-                        send_image(&mut server_stdin, &fastboot_image, &quit).await?
+            }
+        })
+    });
+
+    // Errors end the session too, the board's power is dealt with all the same
+    let result: anyhow::Result<()> = async {
+        while !*get_arc!(quit) {
+            // Stream of "blue text" - status updates from the server
+            if let Ok(bytes_read) = (*get_arc!(server_stderr)).read(&mut buf).await {
+                let s = String::from_utf8_lossy(&buf[..bytes_read]);
+                print!(
+                    "{}\r",
+                    s.split('\n').collect::<Vec<_>>().join("\r\n").blue()
+                );
+                stdout().flush()?;
+            }
+
+            // Msg handler
+            // Read the message header first
+            if (*get_arc!(server_stdout))
+                .read_exact(&mut hdr_buf)
+                .await
+                .is_ok()
+            {
+                let msg = parse_recv_msg(&hdr_buf);
+                let mut msgbuf = vec![0u8; msg.len as usize];
+
+                // Now read the actual data...
+                (*get_arc!(server_stdout)).read_exact(&mut msgbuf).await?;
+
+                // ..and process it
+                match msg.r#type.try_into() {
+                    Ok(Sk8brdMsgs::MsgSelectBoard) => match start_power {
+                        StartPolicy::On => {
+                            send_msg(&mut server_stdin, Sk8brdMsgs::MsgPowerOn, &[]).await?
+                        }
+                        StartPolicy::Cycle => {
+                            tokio::time::sleep(args.power_policy.off_delay()).await;
+                            send_msg(&mut server_stdin, Sk8brdMsgs::MsgPowerOn, &[]).await?
+                        }
+                        StartPolicy::Leave => (),
+                    },
+                    Ok(Sk8brdMsgs::MsgConsole) => {
+                        get_arc!(log).console(&msgbuf)?;
+                        console_print(&msgbuf).await;
+
+                        if let Some(answer) = login.as_mut().and_then(|l| l.console(&msgbuf)) {
+                            send_console(&mut server_stdin, &answer).await?;
+                        }
                     }
-                }
-                Ok(Sk8brdMsgs::MsgFastbootDownload) => (),
-                Ok(Sk8brdMsgs::MsgFastbootBoot) => todo!("MsgFastbootBoot is unused"),
-                Ok(Sk8brdMsgs::MsgStatusUpdate) => todo!("MsgStatusUpdate: implement me!"),
-                Ok(Sk8brdMsgs::MsgVbusOn) => todo!("Unexpected MsgVbusOn"),
-                Ok(Sk8brdMsgs::MsgVbusOff) => todo!("Unexpected MsgVbusOff"),
-                Ok(Sk8brdMsgs::MsgFastbootReboot) => todo!("MsgFastbootReboot is unused"),
-                Ok(Sk8brdMsgs::MsgSendBreak) => todo!("MsgSendBreak: implement me!"),
-                Ok(Sk8brdMsgs::MsgListDevices) => print_string_msg(&msgbuf),
-                Ok(Sk8brdMsgs::MsgBoardInfo) => print_string_msg(&msgbuf),
-                Ok(Sk8brdMsgs::MsgFastbootContinue) => (),
-
-                Ok(m) => todo!("{m:?} is unimplemented, skipping.."),
-                Err(e) => todo!("Received unknown/invalid message: `{e}`"),
-            };
+                    Ok(Sk8brdMsgs::MsgHardReset) => todo!("MsgHardReset is unused"),
+                    Ok(Sk8brdMsgs::MsgPowerOn) => {
+                        event(&log, &mut power_log, LogEvent::PowerOn).await?
+                    }
+                    Ok(Sk8brdMsgs::MsgPowerOff) => {
+                        event(&log, &mut power_log, LogEvent::PowerOff).await?
+                    }
+                    Ok(Sk8brdMsgs::MsgFastbootPresent) => {
+                        if !msgbuf.is_empty() && msgbuf[0] != 0 {
+                            event(&log, &mut power_log, LogEvent::FastbootPresent).await?;
+                            match &fastboot_image {
+                                Some(image) => {
+                                    send_image(&mut server_stdin, image, &quit).await?;
+                                    event(&log, &mut power_log, LogEvent::ImageSent).await?;
+                                }
+                                None => {
+                                    send_fastboot_continue(&mut server_stdin).await?;
+                                    event(&log, &mut power_log, LogEvent::FastbootContinue).await?;
+                                }
+                            }
+                        }
+                    }
+                    Ok(Sk8brdMsgs::MsgFastbootDownload) => (),
+                    Ok(Sk8brdMsgs::MsgFastbootBoot) => todo!("MsgFastbootBoot is unused"),
+                    Ok(Sk8brdMsgs::MsgStatusUpdate) => {
+                        if let Some(status_fifo) = &mut status_fifo {
+                            status_fifo.forward(&msgbuf)?;
+                        }
+
+                        for update in StatusUpdate::parse_all(&msgbuf) {
+                            let update = match update {
+                                Ok(update) => update,
+                                Err(e) => {
+                                    print!("{}\r\n", format!("{e:#}").yellow());
+                                    continue;
+                                }
+                            };
+
+                            if let Some(power_log) = &mut power_log {
+                                power_log.record(&update)?;
+                            }
+                            match &mut sparklines {
+                                Some(sparklines) => {
+                                    for line in sparklines.update(&update) {
+                                        print!("{}\r\n", line.cyan());
+                                    }
+                                }
+                                // Printing the periodic updates would drown the console
+                                None if args.power.is_active() => (),
+                                None => print!("{}\r\n", update.to_string().cyan()),
+                            }
+                        }
+                        stdout().flush()?;
+                    }
+                    Ok(Sk8brdMsgs::MsgVbusOn) => todo!("Unexpected MsgVbusOn"),
+                    Ok(Sk8brdMsgs::MsgVbusOff) => todo!("Unexpected MsgVbusOff"),
+                    Ok(Sk8brdMsgs::MsgFastbootReboot) => todo!("MsgFastbootReboot is unused"),
+                    Ok(Sk8brdMsgs::MsgSendBreak) => todo!("MsgSendBreak: implement me!"),
+                    Ok(Sk8brdMsgs::MsgListDevices) => {
+                        if !board_list.is_complete() && board_list.push(&msgbuf) {
+                            print!("{}\r", board_list.table().replace('\n', "\r\n"));
+                            stdout().flush()?;
+                        }
+                    }
+                    Ok(Sk8brdMsgs::MsgBoardInfo) => {
+                        let board_info = BoardInfo::parse(&args.board, &msgbuf);
+                        print!("{}\r\n", board_info.to_string().replace('\n', "\r\n"));
+                        stdout().flush()?;
+                        *get_arc!(info) = Some(board_info);
+                    }
+                    Ok(Sk8brdMsgs::MsgFastbootContinue) => (),
+
+                    Ok(m) => todo!("{m:?} is unimplemented, skipping.."),
+                    Err(e) => todo!("Received unknown/invalid message: `{e}`"),
+                };
+            }
         }
+
+        Ok(())
     }
+    .await;
 
     // No more keypresses will be useful
     stdin_handler.abort();
+    if let Some(status_poller) = status_poller {
+        status_poller.abort();
+    }
 
     // Pick up the trash
     crossterm::terminal::disable_raw_mode()?;
 
-    // Power off the board on goodbye
-    send_ack(&mut server_stdin, Sk8brdMsgs::MsgPowerOff).await?;
+    // Power off the board on goodbye, unless asked to leave it be
+    if args.power_policy.exit_power.powers_off(result.is_err()) {
+        match send_ack(&mut server_stdin, Sk8brdMsgs::MsgPowerOff).await {
+            Ok(()) => event(&log, &mut power_log, LogEvent::PowerOff).await?,
+            // Don't insist if the connection is what failed in the first place
+            Err(_) if result.is_err() => (),
+            Err(e) => return Err(e),
+        }
+    }
+    result?;
 
     // ssh_disconnect(&mut sess).await?;
 
@@ -0,0 +1,67 @@
+use sk8brd::status::StatusUpdate;
+use std::collections::{BTreeMap, VecDeque};
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// How many samples each sparkline spans
+const WIDTH: usize = 32;
+
+/// Recent current and voltage samples of each channel, drawn as sparklines
+#[derive(Default)]
+pub struct Sparklines {
+    history: BTreeMap<(String, &'static str), VecDeque<f64>>,
+}
+
+fn sparkline(samples: &VecDeque<f64>) -> String {
+    let min = samples.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+    samples
+        .iter()
+        .map(|v| {
+            let level = if max > min {
+                ((v - min) / (max - min) * (BARS.len() - 1) as f64).round() as usize
+            } else {
+                0
+            };
+            BARS[level]
+        })
+        .collect()
+}
+
+impl Sparklines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take in an update, returning a line per channel with current or voltage readings
+    pub fn update(&mut self, update: &StatusUpdate) -> Vec<String> {
+        let mut lines = vec![];
+
+        for channel in update.channels.keys() {
+            let mut line = String::new();
+            for unit in ["mA", "mV"] {
+                let Some(value) = update.value(channel, unit) else {
+                    continue;
+                };
+
+                let samples = self.history.entry((channel.clone(), unit)).or_default();
+                if samples.len() == WIDTH {
+                    samples.pop_front();
+                }
+                samples.push_back(value);
+
+                line.push_str(&format!(
+                    "  {unit} {:<WIDTH$} {value:>7}",
+                    sparkline(samples)
+                ));
+            }
+
+            if !line.is_empty() {
+                lines.push(format!("{channel}{line}"));
+            }
+        }
+
+        lines
+    }
+}
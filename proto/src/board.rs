@@ -0,0 +1,269 @@
+use serde::Serialize;
+
+/// A board offered by the server, as listed in reply to MsgListDevices
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BoardEntry {
+    pub name: String,
+    pub description: String,
+}
+
+impl BoardEntry {
+    /// Parse a `name   description` MsgListDevices payload
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        let line = String::from_utf8_lossy(buf);
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+
+        let (name, description) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        Some(Self {
+            name: name.to_string(),
+            description: description.trim().to_string(),
+        })
+    }
+}
+
+/// Collects the MsgListDevices replies, one per board and terminated by an empty one
+#[derive(Debug, Default)]
+pub struct BoardList {
+    boards: Vec<BoardEntry>,
+    complete: bool,
+}
+
+impl BoardList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a MsgListDevices payload, returning whether the list is complete
+    pub fn push(&mut self, buf: &[u8]) -> bool {
+        if buf.is_empty() {
+            self.complete = true;
+        } else if let Some(board) = BoardEntry::parse(buf) {
+            self.boards.push(board);
+        }
+
+        self.complete
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn boards(&self) -> &[BoardEntry] {
+        &self.boards
+    }
+
+    pub fn into_boards(self) -> Vec<BoardEntry> {
+        self.boards
+    }
+
+    /// The boards as an aligned two-column table, one line per board
+    pub fn table(&self) -> String {
+        let width = self
+            .boards
+            .iter()
+            .map(|b| b.name.len())
+            .chain([5])
+            .max()
+            .unwrap_or_default();
+
+        let mut table = format!("{:<width$}  DESCRIPTION\n", "BOARD");
+        for b in &self.boards {
+            table.push_str(&format!("{:<width$}  {}\n", b.name, b.description));
+        }
+
+        table
+    }
+}
+
+/// What the server tells about a board in reply to MsgBoardInfo
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct BoardInfo {
+    pub name: String,
+    pub description: String,
+    /// Whether USB VBUS can be switched, if the server says
+    pub vbus: Option<bool>,
+    /// Whether status updates are available, if the server says
+    pub status: Option<bool>,
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "yes" | "true" | "on" => Some(true),
+        "0" | "no" | "false" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+impl BoardInfo {
+    /// Parse a MsgBoardInfo payload: either a JSON object or free text, in which
+    /// `vbus: yes`-style lines report the capabilities and the rest describes the board
+    pub fn parse(board: &str, buf: &[u8]) -> Self {
+        let text = String::from_utf8_lossy(buf);
+        let mut info = Self {
+            name: board.to_string(),
+            ..Default::default()
+        };
+
+        if let Ok(serde_json::Value::Object(obj)) = serde_json::from_str(&text) {
+            let flag = |key: &str| match obj.get(key)? {
+                serde_json::Value::Bool(b) => Some(*b),
+                serde_json::Value::String(s) => parse_flag(s),
+                _ => None,
+            };
+
+            if let Some(name) = obj.get("name").and_then(|v| v.as_str()) {
+                info.name = name.to_string();
+            }
+            info.description = obj
+                .get("description")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+            info.vbus = flag("vbus");
+            info.status = flag("status");
+            return info;
+        }
+
+        let mut description = vec![];
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let flag = line
+                .split_once(':')
+                .map(|(k, v)| (k.trim().to_lowercase(), parse_flag(v)));
+
+            match flag {
+                Some((key, Some(value))) if key == "vbus" => info.vbus = Some(value),
+                Some((key, Some(value))) if key == "status" => info.status = Some(value),
+                _ => description.push(line),
+            }
+        }
+        info.description = description.join("\n");
+
+        info
+    }
+
+    /// Whether VBUS controls make sense, i.e. the server didn't say otherwise
+    pub fn supports_vbus(&self) -> bool {
+        self.vbus != Some(false)
+    }
+
+    /// Whether status updates make sense, i.e. the server didn't say otherwise
+    pub fn supports_status(&self) -> bool {
+        self.status != Some(false)
+    }
+}
+
+impl std::fmt::Display for BoardInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flag = |v: Option<bool>| match v {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "unknown",
+        };
+
+        writeln!(f, "Board:       {}", self.name)?;
+        writeln!(f, "Description: {}", self.description)?;
+        writeln!(f, "VBUS:        {}", flag(self.vbus))?;
+        write!(f, "Status:      {}", flag(self.status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn board_entries() {
+        assert_eq!(
+            BoardEntry::parse(b"db845c   Dragonboard 845c, rack 2\n"),
+            Some(BoardEntry {
+                name: "db845c".to_string(),
+                description: "Dragonboard 845c, rack 2".to_string(),
+            })
+        );
+        assert_eq!(
+            BoardEntry::parse(b"rb3"),
+            Some(BoardEntry {
+                name: "rb3".to_string(),
+                description: String::new(),
+            })
+        );
+        assert_eq!(BoardEntry::parse(b" \n"), None);
+    }
+
+    #[test]
+    fn board_list() {
+        let mut list = BoardList::new();
+        assert!(!list.push(b"db845c  Dragonboard 845c"));
+        assert!(!list.push(b"\n"));
+        assert!(!list.push(b"qrb5165-rb3"));
+        assert!(!list.is_complete());
+        assert!(list.push(b""));
+
+        let names: Vec<_> = list.boards().iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["db845c", "qrb5165-rb3"]);
+        assert_eq!(
+            list.table(),
+            "BOARD        DESCRIPTION\n\
+             db845c       Dragonboard 845c\n\
+             qrb5165-rb3  \n"
+        );
+    }
+
+    #[test]
+    fn board_info_json() {
+        let info = BoardInfo::parse(
+            "db845c",
+            br#"{"name": "db845c-2", "description": "Dragonboard", "vbus": true, "status": "no", "extra": [1]}"#,
+        );
+        assert_eq!(
+            info,
+            BoardInfo {
+                name: "db845c-2".to_string(),
+                description: "Dragonboard".to_string(),
+                vbus: Some(true),
+                status: Some(false),
+            }
+        );
+
+        // Unknown keys are ignored, and flags that aren't flags are unknown
+        let info = BoardInfo::parse("db845c", br#"{"vbus": 3, "frobnicate": true}"#);
+        assert_eq!(info.name, "db845c");
+        assert_eq!(info.description, "");
+        assert_eq!(info.vbus, None);
+        assert!(info.supports_vbus());
+    }
+
+    #[test]
+    fn board_info_text() {
+        let info = BoardInfo::parse(
+            "rb3",
+            b"Robotics board\n  VBUS: off\nstatus: maybe\n\nrack 4\n",
+        );
+        assert_eq!(info.description, "Robotics board\nstatus: maybe\nrack 4");
+        assert_eq!(info.vbus, Some(false));
+        assert_eq!(info.status, None);
+        assert!(!info.supports_vbus());
+        assert!(info.supports_status());
+    }
+
+    #[test]
+    fn board_info_malformed_json() {
+        // Broken JSON is just text, and JSON that isn't an object too
+        let info = BoardInfo::parse("rb3", br#"{"vbus": true"#);
+        assert_eq!(info.description, r#"{"vbus": true"#);
+        assert_eq!(info.vbus, None);
+
+        let info = BoardInfo::parse("rb3", b"[1, 2]");
+        assert_eq!(info.description, "[1, 2]");
+        assert_eq!(
+            BoardInfo::parse("rb3", b""),
+            BoardInfo {
+                name: "rb3".to_string(),
+                ..BoardInfo::default()
+            }
+        );
+    }
+}
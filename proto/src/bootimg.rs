@@ -0,0 +1,144 @@
+use anyhow::bail;
+
+const BOOT_MAGIC: &[u8] = b"ANDROID!";
+/// Where all header versions keep theirs
+const HEADER_VERSION_OFFSET: usize = 40;
+
+/// Versions 0-2 split the command line into `cmdline[512]` and `extra_cmdline[1024]`
+const V0_CMDLINE: (usize, usize) = (64, 512);
+const V0_EXTRA_CMDLINE: (usize, usize) = (608, 1024);
+/// Versions 3 and 4 have a single `cmdline[1536]`
+const V3_CMDLINE: (usize, usize) = (44, 1536);
+
+/// Write a NUL-terminated string into a fixed-size header field
+fn write_field(image: &mut [u8], (offset, size): (usize, usize), s: &[u8]) {
+    let field = &mut image[offset..offset + size];
+    field.fill(0);
+    field[..s.len()].copy_from_slice(s);
+}
+
+/// Replace the kernel command line in an Android boot image's header
+pub fn set_cmdline(image: &mut [u8], cmdline: &str) -> anyhow::Result<()> {
+    if !image.starts_with(BOOT_MAGIC) || image.len() < V0_EXTRA_CMDLINE.0 + V0_EXTRA_CMDLINE.1 {
+        bail!("Not an Android boot image, can't set its command line");
+    }
+
+    let version = u32::from_le_bytes(
+        image[HEADER_VERSION_OFFSET..HEADER_VERSION_OFFSET + 4]
+            .try_into()
+            .unwrap(),
+    );
+    let cmdline = cmdline.as_bytes();
+
+    match version {
+        0..=2 => {
+            // Like mkbootimg, spill whatever doesn't fit over into extra_cmdline
+            let (main, extra) = cmdline.split_at(cmdline.len().min(V0_CMDLINE.1 - 1));
+            if extra.len() > V0_EXTRA_CMDLINE.1 - 1 {
+                bail!("The command line is too long for a v{version} boot image");
+            }
+            write_field(image, V0_CMDLINE, main);
+            write_field(image, V0_EXTRA_CMDLINE, extra);
+        }
+        3 | 4 => {
+            if cmdline.len() > V3_CMDLINE.1 - 1 {
+                bail!("The command line is too long for a v{version} boot image");
+            }
+            write_field(image, V3_CMDLINE, cmdline);
+        }
+        _ => bail!("Unsupported boot image header version {version}"),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A header of the given version, with the command line fields full of leftovers
+    fn image(version: u32) -> Vec<u8> {
+        let mut image = vec![0xff; 4096];
+        image[..BOOT_MAGIC.len()].copy_from_slice(BOOT_MAGIC);
+        image[HEADER_VERSION_OFFSET..HEADER_VERSION_OFFSET + 4]
+            .copy_from_slice(&version.to_le_bytes());
+        image
+    }
+
+    fn field(image: &[u8], (offset, size): (usize, usize)) -> &[u8] {
+        &image[offset..offset + size]
+    }
+
+    /// The field's string, making sure the rest of it was cleared
+    fn string(image: &[u8], field_at: (usize, usize)) -> &[u8] {
+        let field = field(image, field_at);
+        let len = field.iter().position(|&b| b == 0).unwrap();
+        assert!(field[len..].iter().all(|&b| b == 0));
+        &field[..len]
+    }
+
+    #[test]
+    fn v0_to_v2() {
+        for version in 0..=2 {
+            let mut image = image(version);
+            set_cmdline(&mut image, "console=ttyMSM0 quiet").unwrap();
+
+            assert_eq!(string(&image, V0_CMDLINE), b"console=ttyMSM0 quiet");
+            assert_eq!(string(&image, V0_EXTRA_CMDLINE), b"");
+            // Nothing outside of the command line fields is touched
+            assert_eq!(image[V0_CMDLINE.0 - 1], 0xff);
+            assert_eq!(image[V0_EXTRA_CMDLINE.0 + V0_EXTRA_CMDLINE.1], 0xff);
+        }
+    }
+
+    #[test]
+    fn v0_split() {
+        let cmdline: String = (0..600).map(|i| (b'a' + (i % 26) as u8) as char).collect();
+        let mut image = image(1);
+        set_cmdline(&mut image, &cmdline).unwrap();
+
+        assert_eq!(string(&image, V0_CMDLINE), &cmdline.as_bytes()[..511]);
+        assert_eq!(string(&image, V0_EXTRA_CMDLINE), &cmdline.as_bytes()[511..]);
+    }
+
+    #[test]
+    fn v0_too_long() {
+        let mut image = image(2);
+        set_cmdline(&mut image, &"x".repeat(511 + 1023)).unwrap();
+        assert_eq!(string(&image, V0_EXTRA_CMDLINE).len(), 1023);
+
+        let e = set_cmdline(&mut image, &"x".repeat(511 + 1024)).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "The command line is too long for a v2 boot image"
+        );
+    }
+
+    #[test]
+    fn v3_and_v4() {
+        for version in [3, 4] {
+            let mut image = image(version);
+            set_cmdline(&mut image, "console=ttyMSM0").unwrap();
+            assert_eq!(string(&image, V3_CMDLINE), b"console=ttyMSM0");
+            assert_eq!(image[V3_CMDLINE.0 + V3_CMDLINE.1], 0xff);
+
+            set_cmdline(&mut image, &"x".repeat(1535)).unwrap();
+            assert_eq!(string(&image, V3_CMDLINE).len(), 1535);
+            assert!(set_cmdline(&mut image, &"x".repeat(1536)).is_err());
+        }
+    }
+
+    #[test]
+    fn not_a_boot_image() {
+        let mut magic = image(0);
+        magic[0] = b'a';
+        assert!(set_cmdline(&mut magic, "quiet").is_err());
+
+        let mut short = image(0);
+        short.truncate(V0_EXTRA_CMDLINE.0 + V0_EXTRA_CMDLINE.1 - 1);
+        assert!(set_cmdline(&mut short, "quiet").is_err());
+
+        let e = set_cmdline(&mut image(5), "quiet").unwrap_err();
+        assert_eq!(e.to_string(), "Unsupported boot image header version 5");
+    }
+}
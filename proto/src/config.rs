@@ -0,0 +1,374 @@
+use crate::CDBA_SERVER_BIN_NAME;
+use crate::login::{Credentials, LoginArgs};
+use anyhow::Context;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_PORT: &str = "22";
+pub const DEFAULT_USER: &str = "cdba";
+
+/// Where to find the server. Each of these can also come from the environment, or else from the
+/// config file: flags beat environment variables, which beat the config file.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct FarmArgs {
+    /// Farm to connect to: a host, or the name of a farm in the config file
+    #[arg(short, env = "SK8BRD_FARM")]
+    pub farm: Option<String>,
+
+    /// SSH port [default: 22]
+    #[arg(short, env = "SK8BRD_PORT")]
+    pub port: Option<String>,
+
+    /// SSH user [default: cdba]
+    #[arg(short, env = "SK8BRD_USER")]
+    pub user: Option<String>,
+
+    /// Reach the farm through this SSH jump host, as [user@]host[:port]
+    #[arg(short = 'J', long, env = "SK8BRD_JUMP")]
+    pub jump: Option<String>,
+
+    /// Path of the server binary on the farm [default: cdba-server]
+    #[arg(long, env = "SK8BRD_SERVER")]
+    pub server: Option<String>,
+
+    /// Config file [default: ~/.config/sk8brd/config.toml]
+    #[arg(long, env = "SK8BRD_CONFIG")]
+    pub config: Option<PathBuf>,
+}
+
+/// A farm, as resolved from the flags, the environment and the config file
+#[derive(Clone, Debug)]
+pub struct Farm {
+    pub host: String,
+    pub port: String,
+    pub user: String,
+    pub jump: Option<String>,
+    pub server: String,
+}
+
+impl Farm {
+    /// `host:port`, as the SSH connection wants it
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FarmConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    /// SSH jump host, as `[user@]host[:port]`
+    pub jump: Option<String>,
+    /// Path of the server binary on the farm
+    pub server: Option<String>,
+}
+
+/// A short name for a board, carrying its defaults
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BoardAlias {
+    /// The board's name on the farm, the alias itself if not given
+    pub board: Option<String>,
+    /// The farm the board is on
+    pub farm: Option<String>,
+    /// Boot image to send when none is given
+    pub image: Option<PathBuf>,
+    /// Kernel command line to put in the boot image
+    pub cmdline: Option<String>,
+    /// Log in at the getty prompt as `user[:password]`
+    pub login: Option<String>,
+    pub login_prompt: Option<String>,
+    pub password_prompt: Option<String>,
+}
+
+/// `~/.config/sk8brd/config.toml`
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The farm to use when neither the flags nor the board say
+    pub default_farm: Option<String>,
+    #[serde(default)]
+    pub farms: BTreeMap<String, FarmConfig>,
+    #[serde(default)]
+    pub boards: BTreeMap<String, BoardAlias>,
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// `$XDG_CONFIG_HOME/sk8brd/config.toml`, falling back to `~/.config`
+pub fn default_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => Path::new(&std::env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(dir.join("sk8brd").join("config.toml"))
+}
+
+impl Config {
+    /// Load the given config file, or the default one if it exists
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn alias(&self, board: &str) -> Option<&BoardAlias> {
+        self.boards.get(board)
+    }
+
+    /// The name the farm knows the board by
+    pub fn board_name(&self, board: &str) -> String {
+        self.alias(board)
+            .and_then(|a| a.board.clone())
+            .unwrap_or_else(|| board.to_string())
+    }
+}
+
+impl FarmArgs {
+    /// Fill in whatever the flags and the environment left out from the config file, looking
+    /// the farm up by name (or else the board's farm, or else the default one), and then from
+    /// the built-in defaults
+    pub fn resolve(&mut self, config: &Config, alias: Option<&BoardAlias>) {
+        let name = self
+            .farm
+            .clone()
+            .or_else(|| alias.and_then(|a| a.farm.clone()))
+            .or_else(|| config.default_farm.clone());
+
+        match name.as_ref().and_then(|n| config.farms.get(n)) {
+            Some(fc) => {
+                self.farm = Some(fc.host.clone());
+                self.port = self.port.take().or(fc.port.map(|p| p.to_string()));
+                self.user = self.user.take().or(fc.user.clone());
+                self.jump = self.jump.take().or(fc.jump.clone());
+                self.server = self.server.take().or(fc.server.clone());
+            }
+            None => self.farm = name,
+        }
+
+        self.port.get_or_insert_with(|| DEFAULT_PORT.to_string());
+        self.user.get_or_insert_with(|| DEFAULT_USER.to_string());
+        self.server
+            .get_or_insert_with(|| CDBA_SERVER_BIN_NAME.to_string());
+    }
+
+    /// The farm to connect to, if one was given anywhere
+    pub fn farm(&self) -> Option<Farm> {
+        Some(Farm {
+            host: self.farm.clone()?,
+            port: self
+                .port
+                .clone()
+                .unwrap_or_else(|| DEFAULT_PORT.to_string()),
+            user: self
+                .user
+                .clone()
+                .unwrap_or_else(|| DEFAULT_USER.to_string()),
+            jump: self.jump.clone(),
+            server: self
+                .server
+                .clone()
+                .unwrap_or_else(|| CDBA_SERVER_BIN_NAME.to_string()),
+        })
+    }
+}
+
+impl BoardAlias {
+    pub fn image(&self) -> Option<PathBuf> {
+        self.image.as_deref().map(expand_home)
+    }
+
+    /// Use the alias' login settings where the flags left them unset
+    pub fn apply_login(&self, login: &mut LoginArgs) -> anyhow::Result<()> {
+        if login.login.is_none() {
+            login.login = self
+                .login
+                .as_deref()
+                .map(|l| l.parse::<Credentials>().unwrap());
+        }
+
+        if login.login_prompt.is_none()
+            && let Some(prompt) = &self.login_prompt
+        {
+            login.login_prompt =
+                Some(Regex::new(prompt).context("Invalid login_prompt in the config file")?);
+        }
+        if login.password_prompt.is_none()
+            && let Some(prompt) = &self.password_prompt
+        {
+            login.password_prompt =
+                Some(Regex::new(prompt).context("Invalid password_prompt in the config file")?);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    const CONFIG: &str = r#"
+default_farm = "home"
+
+[farms.home]
+host = "home.example.com"
+
+[farms.lab]
+host = "lab.example.com"
+port = 2222
+user = "labuser"
+jump = "gw.example.com"
+server = "/opt/cdba/cdba-server"
+
+[boards.rb3]
+board = "qrb5165-rb3"
+farm = "lab"
+image = "~/images/rb3.img"
+login = "root:secret"
+login_prompt = "rb3 login: $"
+"#;
+
+    fn config() -> Config {
+        toml::from_str(CONFIG).unwrap()
+    }
+
+    fn farm(args: FarmArgs, alias: Option<&str>) -> Farm {
+        let config = config();
+        let mut args = args;
+        args.resolve(&config, alias.and_then(|a| config.alias(a)));
+        args.farm().unwrap()
+    }
+
+    #[derive(Parser)]
+    struct TestArgs {
+        #[command(flatten)]
+        farm: FarmArgs,
+    }
+
+    #[test]
+    fn flags_beat_env_beat_config() {
+        // The only test touching the environment, so that none of the others sees it
+        // SAFETY: no other thread reads or writes these variables
+        unsafe {
+            std::env::set_var("SK8BRD_PORT", "2200");
+            std::env::set_var("SK8BRD_USER", "envuser");
+        }
+        let args = TestArgs::try_parse_from(["test", "-f", "lab", "-p", "22"]).unwrap();
+        unsafe {
+            std::env::remove_var("SK8BRD_PORT");
+            std::env::remove_var("SK8BRD_USER");
+        }
+
+        let farm = farm(args.farm, None);
+        assert_eq!(farm.host, "lab.example.com");
+        assert_eq!(farm.port, "22");
+        assert_eq!(farm.user, "envuser");
+        assert_eq!(farm.jump.as_deref(), Some("gw.example.com"));
+        assert_eq!(farm.server, "/opt/cdba/cdba-server");
+    }
+
+    #[test]
+    fn config_beats_defaults() {
+        let lab = FarmArgs {
+            farm: Some("lab".to_string()),
+            ..FarmArgs::default()
+        };
+        assert_eq!(farm(lab, None).address(), "lab.example.com:2222");
+
+        let home = farm(FarmArgs::default(), None);
+        assert_eq!(home.address(), "home.example.com:22");
+        assert_eq!(home.user, DEFAULT_USER);
+        assert_eq!(home.jump, None);
+        assert_eq!(home.server, CDBA_SERVER_BIN_NAME);
+    }
+
+    #[test]
+    fn farm_resolution() {
+        // The board's farm beats the default one, but not the flags
+        assert_eq!(
+            farm(FarmArgs::default(), Some("rb3")).host,
+            "lab.example.com"
+        );
+        let home = FarmArgs {
+            farm: Some("home".to_string()),
+            ..FarmArgs::default()
+        };
+        assert_eq!(farm(home, Some("rb3")).host, "home.example.com");
+
+        // Anything that isn't a farm's name is a host
+        let host = FarmArgs {
+            farm: Some("10.0.0.1".to_string()),
+            ..FarmArgs::default()
+        };
+        assert_eq!(farm(host, None).address(), "10.0.0.1:22");
+
+        let mut nowhere = FarmArgs::default();
+        nowhere.resolve(&Config::default(), None);
+        assert!(nowhere.farm().is_none());
+    }
+
+    #[test]
+    fn board_aliases() {
+        let config = config();
+        assert_eq!(config.board_name("rb3"), "qrb5165-rb3");
+        assert_eq!(config.board_name("db845c"), "db845c");
+
+        let image = config.alias("rb3").unwrap().image().unwrap();
+        assert!(image.ends_with("images/rb3.img"));
+        if std::env::var_os("HOME").is_some() {
+            assert!(!image.starts_with("~"));
+        }
+    }
+
+    #[test]
+    fn alias_login_fills_in_unset_values() {
+        let alias = config().boards.remove("rb3").unwrap();
+
+        let mut login = LoginArgs::default();
+        alias.apply_login(&mut login).unwrap();
+        assert_eq!(login.login, Some("root:secret".parse().unwrap()));
+        assert_eq!(login.login_prompt.unwrap().as_str(), "rb3 login: $");
+        assert!(login.password_prompt.is_none());
+
+        // Explicit values win, even when they're the defaults
+        let mut login = LoginArgs {
+            login: Some("user".parse().unwrap()),
+            login_prompt: Some(Regex::new(crate::login::DEFAULT_LOGIN_PROMPT).unwrap()),
+            password_prompt: None,
+        };
+        alias.apply_login(&mut login).unwrap();
+        assert_eq!(login.login, Some("user".parse().unwrap()));
+        assert_eq!(
+            login.login_prompt.unwrap().as_str(),
+            crate::login::DEFAULT_LOGIN_PROMPT
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(toml::from_str::<Config>("[farms.a]\nhost = \"a\"\nbogus = 1\n").is_err());
+        assert!(toml::from_str::<Config>("defualt_farm = \"a\"\n").is_err());
+        assert!(toml::from_str::<Config>("[boards.x]\nimage = 3\n").is_err());
+    }
+}
@@ -0,0 +1,111 @@
+/// Reassembles the MsgConsole byte stream into lines
+#[derive(Debug, Default)]
+pub struct ConsoleLines {
+    partial: Vec<u8>,
+}
+
+impl ConsoleLines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a MsgConsole payload, returning the lines it completed
+    pub fn push(&mut self, buf: &[u8]) -> Vec<String> {
+        let mut lines = vec![];
+
+        for &c in buf {
+            match c {
+                b'\n' => {
+                    lines.push(String::from_utf8_lossy(&self.partial).into_owned());
+                    self.partial.clear();
+                }
+                b'\r' => (),
+                _ => self.partial.push(c),
+            }
+        }
+
+        lines
+    }
+
+    /// The line currently being assembled, e.g. a prompt not followed by a newline
+    pub fn partial(&self) -> String {
+        String::from_utf8_lossy(&self.partial).into_owned()
+    }
+}
+
+/// Console output waiting to be matched against, of which only the tail needs to be kept around
+/// while nothing matches
+#[derive(Clone, Debug)]
+pub struct ConsoleTail {
+    text: String,
+    window: usize,
+}
+
+impl ConsoleTail {
+    /// Keep at most `window` bytes when trimmed
+    pub fn new(window: usize) -> Self {
+        Self {
+            text: String::new(),
+            window,
+        }
+    }
+
+    /// Append a MsgConsole payload, dropping carriage returns
+    pub fn push(&mut self, buf: &[u8]) {
+        self.text
+            .push_str(&String::from_utf8_lossy(buf).replace('\r', ""));
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Forget everything up to `end`, e.g. the end of a match
+    pub fn consume(&mut self, end: usize) {
+        self.text.drain(..end);
+    }
+
+    /// Forget all but the last `window` bytes, without splitting a character
+    pub fn trim(&mut self) {
+        if self.text.len() > self.window {
+            let mut cut = self.text.len() - self.window;
+            while !self.text.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.text.drain(..cut);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let mut lines = ConsoleLines::new();
+        assert!(lines.push(b"Booting\r").is_empty());
+        assert_eq!(lines.push(b"\nok\nlogin: "), ["Booting", "ok"]);
+        assert_eq!(lines.partial(), "login: ");
+    }
+
+    #[test]
+    fn tail() {
+        let mut tail = ConsoleTail::new(4);
+        tail.push(b"ab\r\ncd");
+        assert_eq!(tail.as_str(), "ab\ncd");
+
+        tail.consume(3);
+        assert_eq!(tail.as_str(), "cd");
+        tail.trim();
+        assert_eq!(tail.as_str(), "cd");
+
+        tail.push("xü!".as_bytes());
+        tail.trim();
+        assert_eq!(tail.as_str(), "xü!");
+        tail.push(b"??");
+        // Cutting at the window would split the ü
+        tail.trim();
+        assert_eq!(tail.as_str(), "!??");
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use {
+    std::ffi::CString,
+    std::fs::OpenOptions,
+    std::io::Write,
+    std::os::unix::ffi::OsStrExt,
+    std::os::unix::fs::{FileTypeExt, OpenOptionsExt},
+};
+
+/// A named pipe the status updates are forwarded to as newline-delimited JSON, like cdba's `-S`.
+/// Nothing ever waits on the reader: updates are dropped while no one is reading (or the
+/// reader lags behind), and a reader that went away is replaced by whoever opens the pipe next.
+/// Named pipes being a Unix thing, asking for one fails elsewhere.
+#[cfg_attr(not(unix), allow(dead_code))]
+pub struct StatusFifo {
+    path: PathBuf,
+    pipe: Option<File>,
+}
+
+#[cfg(unix)]
+impl StatusFifo {
+    /// Use the named pipe at `path`, creating it if need be
+    pub fn new(path: &Path) -> io::Result<Self> {
+        match path.metadata() {
+            Ok(meta) if meta.file_type().is_fifo() => (),
+            Ok(_) => {
+                return Err(io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} exists and isn't a FIFO", path.display()),
+                ));
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let c_path = CString::new(path.as_os_str().as_bytes())?;
+                // SAFETY: c_path is a valid NUL-terminated string
+                if unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) } != 0 {
+                    let e = io::Error::last_os_error();
+                    // Another board of the same run may have just beaten us to it
+                    if e.kind() != ErrorKind::AlreadyExists {
+                        return Err(e);
+                    }
+                }
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            pipe: None,
+        })
+    }
+
+    /// Get hold of the writing end, which only succeeds while someone is reading
+    fn pipe(&mut self) -> Option<&mut File> {
+        if self.pipe.is_none() {
+            self.pipe = OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&self.path)
+                .ok();
+        }
+
+        self.pipe.as_mut()
+    }
+
+    /// Forward a MsgStatusUpdate payload, a line at a time
+    pub fn forward(&mut self, buf: &[u8]) -> io::Result<()> {
+        let text = String::from_utf8_lossy(buf);
+
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let Some(pipe) = self.pipe() else {
+                return Ok(());
+            };
+
+            // Lines shorter than PIPE_BUF are written whole or not at all
+            match pipe.write_all(format!("{line}\n").as_bytes()) {
+                Ok(()) => (),
+                // The reader lags behind, drop the line
+                Err(e) if e.kind() == ErrorKind::WouldBlock => (),
+                // The reader went away, wait for the next one
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => self.pipe = None,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+impl StatusFifo {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "Can't use {}: status FIFOs are only supported on Unix",
+                path.display()
+            ),
+        ))
+    }
+
+    pub fn forward(&mut self, _buf: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+}
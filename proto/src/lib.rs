@@ -1,18 +1,27 @@
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
-use std::io::{Write, stdout};
+use std::io::{Write, stderr, stdout};
 use std::mem::size_of;
 use std::sync::Arc;
-use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::sync::Mutex;
 
+pub mod board;
+pub mod bootimg;
+pub mod config;
+pub mod console;
+pub mod fifo;
+pub mod log;
+pub mod login;
+pub mod power;
 #[cfg(feature = "ssh")]
 pub mod ssh;
+pub mod status;
 
 pub const CDBA_SERVER_BIN_NAME: &str = "cdba-server";
 
 #[repr(u8)]
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 #[non_exhaustive]
 pub enum Sk8brdMsgs {
     MsgSelectBoard = 1,
@@ -102,6 +111,20 @@ pub fn parse_recv_msg(buf: &[u8]) -> Sk8brdMsg {
     msg
 }
 
+/// Read a single message (header and payload) from the server
+pub async fn recv_msg(
+    read_source: &mut (impl AsyncRead + std::marker::Unpin),
+) -> anyhow::Result<(Sk8brdMsg, Vec<u8>)> {
+    let mut hdr_buf = [0u8; MSG_HDR_SIZE];
+    read_source.read_exact(&mut hdr_buf).await?;
+
+    let msg = parse_recv_msg(&hdr_buf);
+    let mut msgbuf = vec![0u8; msg.len as usize];
+    read_source.read_exact(&mut msgbuf).await?;
+
+    Ok((msg, msgbuf))
+}
+
 pub async fn console_print(buf: &[u8]) {
     print!("{}", String::from_utf8_lossy(buf));
     stdout().flush().unwrap();
@@ -125,8 +148,8 @@ pub async fn send_image(
 
         if percent_done != last_percent_done {
             let s = format!("Sending image: {percent_done}%\r");
-            print!("{}", s.green());
-            stdout().flush()?;
+            eprint!("{}", s.green());
+            stderr().flush()?;
         }
 
         send_msg(write_sink, Sk8brdMsgs::MsgFastbootDownload, chunk).await?;
@@ -135,9 +158,9 @@ pub async fn send_image(
         last_percent_done = percent_done;
 
         if bytes_sent == buf.len() {
-            print!("\r{}\r", " ".repeat(80));
-            print!("{}\r\n", String::from("Image sent!").green());
-            stdout().flush()?;
+            eprint!("\r{}\r", " ".repeat(80));
+            eprint!("{}\r\n", String::from("Image sent!").green());
+            stderr().flush()?;
         }
     }
 
@@ -151,12 +174,37 @@ pub async fn select_brd(
     send_msg(write_sink, Sk8brdMsgs::MsgSelectBoard, name.as_bytes()).await
 }
 
+pub async fn request_board_info(
+    write_sink: &mut Arc<Mutex<impl AsyncWrite + std::marker::Unpin>>,
+    name: &str,
+) -> anyhow::Result<()> {
+    send_msg(write_sink, Sk8brdMsgs::MsgBoardInfo, name.as_bytes()).await
+}
+
+pub async fn request_status_update(
+    write_sink: &mut Arc<Mutex<impl AsyncWrite + std::marker::Unpin>>,
+) -> anyhow::Result<()> {
+    send_ack(write_sink, Sk8brdMsgs::MsgStatusUpdate).await
+}
+
 pub async fn send_break(
     write_sink: &mut Arc<Mutex<impl AsyncWrite + std::marker::Unpin>>,
 ) -> anyhow::Result<()> {
     send_ack(write_sink, Sk8brdMsgs::MsgSendBreak).await
 }
 
+pub async fn send_fastboot_continue(
+    write_sink: &mut Arc<Mutex<impl AsyncWrite + std::marker::Unpin>>,
+) -> anyhow::Result<()> {
+    send_ack(write_sink, Sk8brdMsgs::MsgFastbootContinue).await
+}
+
+pub async fn send_fastboot_reboot(
+    write_sink: &mut Arc<Mutex<impl AsyncWrite + std::marker::Unpin>>,
+) -> anyhow::Result<()> {
+    send_ack(write_sink, Sk8brdMsgs::MsgFastbootReboot).await
+}
+
 pub async fn send_console(
     write_sink: &mut Arc<Mutex<impl AsyncWrite + std::marker::Unpin>>,
     buf: &[u8],
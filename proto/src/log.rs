@@ -0,0 +1,214 @@
+use chrono::Local;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// How many rotated logs (FILE.1, FILE.2, ...) are kept around
+pub const LOG_ROTATE_KEEP: usize = 5;
+
+/// What the timestamped log's line prefixes are relative to
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogTimestamps {
+    /// Local wall clock time
+    #[default]
+    Wall,
+    /// Seconds since the board was last powered on
+    PowerOn,
+    /// Seconds since the image was last sent
+    ImageSent,
+}
+
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct LogArgs {
+    /// Write the raw console stream to this file
+    #[arg(long)]
+    pub log: Option<PathBuf>,
+
+    /// Write timestamped console lines and board events to this file
+    #[arg(long)]
+    pub log_timestamped: Option<PathBuf>,
+
+    /// Reference point for the timestamped log
+    #[arg(long, value_enum, default_value_t)]
+    pub log_timestamps: LogTimestamps,
+
+    /// Rotate the logs once they grow past this many bytes
+    #[arg(long)]
+    pub log_max_size: Option<u64>,
+}
+
+/// Things happening to the board, marked inline in the timestamped log
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogEvent {
+    PowerOn,
+    PowerOff,
+    FastbootPresent,
+    ImageSent,
+    FastbootContinue,
+    BreakSent,
+}
+
+impl fmt::Display for LogEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            LogEvent::PowerOn => "power on",
+            LogEvent::PowerOff => "power off",
+            LogEvent::FastbootPresent => "fastboot present",
+            LogEvent::ImageSent => "image sent",
+            LogEvent::FastbootContinue => "fastboot continue",
+            LogEvent::BreakSent => "break sent",
+        };
+        f.write_str(s)
+    }
+}
+
+/// A log file that is rotated once it grows past a size limit
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_size: Option<u64>,
+}
+
+impl LogFile {
+    fn create(path: &Path, max_size: Option<u64>) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: File::create(path)?,
+            written: 0,
+            max_size,
+        })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for n in (1..LOG_ROTATE_KEEP).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                fs::rename(from, self.rotated_path(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+
+        self.file = File::create(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        if self
+            .max_size
+            .is_some_and(|max| self.written > 0 && self.written >= max)
+        {
+            self.rotate()?;
+        }
+
+        self.file.write_all(buf)?;
+        self.written += buf.len() as u64;
+        Ok(())
+    }
+}
+
+/// Console capture, both raw and line-timestamped. Without any files configured, it does nothing.
+pub struct ConsoleLog {
+    raw: Option<LogFile>,
+    timestamped: Option<LogFile>,
+    timestamps: LogTimestamps,
+
+    start: Instant,
+    power_on: Option<Instant>,
+    image_sent: Option<Instant>,
+    at_line_start: bool,
+}
+
+impl ConsoleLog {
+    pub fn new(args: &LogArgs) -> io::Result<Self> {
+        Ok(Self {
+            raw: args
+                .log
+                .as_deref()
+                .map(|path| LogFile::create(path, args.log_max_size))
+                .transpose()?,
+            timestamped: args
+                .log_timestamped
+                .as_deref()
+                .map(|path| LogFile::create(path, args.log_max_size))
+                .transpose()?,
+            timestamps: args.log_timestamps,
+            start: Instant::now(),
+            power_on: None,
+            image_sent: None,
+            at_line_start: true,
+        })
+    }
+
+    fn prefix(&self) -> String {
+        let since = match self.timestamps {
+            LogTimestamps::Wall => {
+                return format!("[{}] ", Local::now().format("%Y-%m-%d %H:%M:%S%.3f"));
+            }
+            LogTimestamps::PowerOn => self.power_on,
+            LogTimestamps::ImageSent => self.image_sent,
+        };
+
+        // Until the reference point is reached, count from the start of the log
+        let elapsed = since.unwrap_or(self.start).elapsed();
+        format!("[{:>10.3}] ", elapsed.as_secs_f64())
+    }
+
+    /// Record a MsgConsole payload
+    pub fn console(&mut self, buf: &[u8]) -> io::Result<()> {
+        if let Some(raw) = &mut self.raw {
+            raw.write(buf)?;
+        }
+
+        if self.timestamped.is_none() {
+            return Ok(());
+        }
+
+        let mut out = Vec::with_capacity(buf.len());
+        for &c in buf {
+            if self.at_line_start {
+                out.extend_from_slice(self.prefix().as_bytes());
+                self.at_line_start = false;
+            }
+
+            out.push(c);
+            self.at_line_start = c == b'\n';
+        }
+
+        if let Some(timestamped) = &mut self.timestamped {
+            timestamped.write(&out)?;
+        }
+
+        Ok(())
+    }
+
+    /// Mark an event in the timestamped log
+    pub fn event(&mut self, event: LogEvent) -> io::Result<()> {
+        match event {
+            LogEvent::PowerOn => self.power_on = Some(Instant::now()),
+            LogEvent::ImageSent => self.image_sent = Some(Instant::now()),
+            _ => (),
+        }
+
+        let line = format!(
+            "{}{}--- {event} ---\n",
+            if self.at_line_start { "" } else { "\n" },
+            self.prefix()
+        );
+        self.at_line_start = true;
+
+        match &mut self.timestamped {
+            Some(timestamped) => timestamped.write(line.as_bytes()),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::console::ConsoleTail;
+use regex::Regex;
+use std::convert::Infallible;
+use std::str::FromStr;
+
+/// While waiting for a prompt, only the tail of the output is of any interest
+const PROMPT_SEARCH_WINDOW: usize = 4096;
+
+pub const DEFAULT_LOGIN_PROMPT: &str = r"login: *$";
+pub const DEFAULT_PASSWORD_PROMPT: &str = r"[Pp]assword: *$";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Credentials {
+    pub user: String,
+    pub password: Option<String>,
+}
+
+/// Parses `user[:password]`
+impl FromStr for Credentials {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.split_once(':') {
+            Some((user, password)) => Self {
+                user: user.to_string(),
+                password: Some(password.to_string()),
+            },
+            None => Self {
+                user: s.to_string(),
+                password: None,
+            },
+        })
+    }
+}
+
+/// The prompts are only set when given explicitly, the defaults apply otherwise
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct LoginArgs {
+    /// Log in at the getty prompt as user[:password]
+    #[arg(long)]
+    pub login: Option<Credentials>,
+
+    /// Regex matching the login prompt [default: "login: *$"]
+    #[arg(long, value_parser = Regex::new)]
+    pub login_prompt: Option<Regex>,
+
+    /// Regex matching the password prompt [default: "[Pp]assword: *$"]
+    #[arg(long, value_parser = Regex::new)]
+    pub password_prompt: Option<Regex>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    User,
+    Password,
+    Done,
+}
+
+/// Answers the login and password prompts showing up on the console
+#[derive(Debug)]
+pub struct AutoLogin {
+    credentials: Credentials,
+    login_prompt: Regex,
+    password_prompt: Regex,
+    state: State,
+    /// Console output not consumed by a prompt yet
+    pending: ConsoleTail,
+}
+
+impl AutoLogin {
+    /// Set up the login, if any was asked for
+    pub fn new(args: &LoginArgs) -> Option<Self> {
+        Some(Self {
+            credentials: args.login.clone()?,
+            login_prompt: args
+                .login_prompt
+                .clone()
+                .unwrap_or_else(|| Regex::new(DEFAULT_LOGIN_PROMPT).unwrap()),
+            password_prompt: args
+                .password_prompt
+                .clone()
+                .unwrap_or_else(|| Regex::new(DEFAULT_PASSWORD_PROMPT).unwrap()),
+            state: State::User,
+            pending: ConsoleTail::new(PROMPT_SEARCH_WINDOW),
+        })
+    }
+
+    /// Whether all the credentials were sent
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Feed console output, returning what to answer the console with
+    pub fn console(&mut self, buf: &[u8]) -> Option<Vec<u8>> {
+        if self.is_done() {
+            return None;
+        }
+
+        self.pending.push(buf);
+
+        let prompt = match self.state {
+            State::User => &self.login_prompt,
+            State::Password => &self.password_prompt,
+            State::Done => return None,
+        };
+
+        let Some(m) = prompt.find(self.pending.as_str()) else {
+            self.pending.trim();
+            return None;
+        };
+        self.pending.consume(m.end());
+
+        let answer = match self.state {
+            State::User => {
+                self.state = match self.credentials.password {
+                    Some(_) => State::Password,
+                    None => State::Done,
+                };
+                &self.credentials.user
+            }
+            _ => {
+                self.state = State::Done;
+                self.credentials.password.as_deref().unwrap_or_default()
+            }
+        };
+
+        Some(format!("{answer}\n").into_bytes())
+    }
+}
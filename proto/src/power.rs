@@ -0,0 +1,182 @@
+use crate::status::StatusUpdate;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Seconds between the status updates, unless told otherwise
+pub const DEFAULT_POWER_INTERVAL: f64 = 1.0;
+
+/// A finite, non-negative number of seconds
+fn parse_secs(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(secs) if secs.is_finite() && secs >= 0.0 => Ok(secs),
+        _ => Err(format!("`{s}` isn't a valid number of seconds")),
+    }
+}
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct PowerArgs {
+    /// Record the board's power measurements (status updates) to this CSV file
+    #[arg(long)]
+    pub power_log: Option<PathBuf>,
+
+    /// Forward the status updates as newline-delimited JSON to this named pipe (created if missing,
+    /// Unix only)
+    #[arg(long)]
+    pub status_fifo: Option<PathBuf>,
+
+    /// Seconds between the status updates requested for the power log and the status FIFO
+    #[arg(long, default_value_t = DEFAULT_POWER_INTERVAL, value_parser = parse_secs)]
+    pub power_interval: f64,
+}
+
+/// The same as the flags' defaults
+impl Default for PowerArgs {
+    fn default() -> Self {
+        Self {
+            power_log: None,
+            status_fifo: None,
+            power_interval: DEFAULT_POWER_INTERVAL,
+        }
+    }
+}
+
+impl PowerArgs {
+    /// Whether status updates are to be requested periodically
+    pub fn is_active(&self) -> bool {
+        self.power_log.is_some() || self.status_fifo.is_some()
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f64(self.power_interval.max(0.1))
+    }
+}
+
+/// What to do with the board's power before booting it
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum StartPolicy {
+    /// Power the board on
+    #[default]
+    On,
+    /// Power the board off first, wait for --power-off-delay, then power it on
+    Cycle,
+    /// Leave the power alone, e.g. to pick up a board left running
+    Leave,
+}
+
+/// What to do with the board's power when leaving
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ExitPolicy {
+    /// Power the board off
+    #[default]
+    Off,
+    /// Leave the board running, to attach to it later
+    Leave,
+    /// Only power the board off if the run failed
+    OffOnFailure,
+}
+
+impl ExitPolicy {
+    pub fn powers_off(self, failed: bool) -> bool {
+        match self {
+            ExitPolicy::Off => true,
+            ExitPolicy::Leave => false,
+            ExitPolicy::OffOnFailure => failed,
+        }
+    }
+}
+
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct PowerPolicyArgs {
+    /// What to do with the board's power before booting it
+    #[arg(long, value_enum, default_value_t)]
+    pub start_power: StartPolicy,
+
+    /// Shorthand for --start-power cycle
+    #[arg(long, default_value_t = false)]
+    pub power_cycle: bool,
+
+    /// Seconds to keep the board powered off when power cycling it
+    #[arg(long, default_value_t = 0.0, value_parser = parse_secs)]
+    pub power_off_delay: f64,
+
+    /// What to do with the board's power when leaving
+    #[arg(long, value_enum, default_value_t)]
+    pub exit_power: ExitPolicy,
+}
+
+impl PowerPolicyArgs {
+    pub fn start(&self) -> StartPolicy {
+        if self.power_cycle {
+            StartPolicy::Cycle
+        } else {
+            self.start_power
+        }
+    }
+
+    pub fn off_delay(&self) -> Duration {
+        Duration::from_secs_f64(self.power_off_delay)
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// A CSV file with a row per measurement and channel, tagged with the last milestone reached:
+/// `time,server_ts,channel,unit,value,milestone`. Milestones get a row of their own, with the
+/// measurement columns left empty.
+pub struct PowerLog {
+    file: BufWriter<File>,
+    start: Instant,
+    milestone: String,
+}
+
+impl PowerLog {
+    pub fn new(path: &Path) -> std::io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "time,server_ts,channel,unit,value,milestone")?;
+        file.flush()?;
+
+        Ok(Self {
+            file,
+            start: Instant::now(),
+            milestone: String::new(),
+        })
+    }
+
+    pub fn record(&mut self, update: &StatusUpdate) -> std::io::Result<()> {
+        let time = self.start.elapsed().as_secs_f64();
+        let ts = update.ts.map(|ts| format!("{ts:.3}")).unwrap_or_default();
+
+        for (channel, values) in &update.channels {
+            for (unit, value) in values {
+                writeln!(
+                    self.file,
+                    "{time:.3},{ts},{},{},{value},{}",
+                    csv_field(channel),
+                    csv_field(unit),
+                    csv_field(&self.milestone)
+                )?;
+            }
+        }
+        self.file.flush()
+    }
+
+    /// Note that a milestone was reached, subsequent measurements are tagged with it
+    pub fn milestone(&mut self, name: &str) -> std::io::Result<()> {
+        self.milestone = name.to_string();
+        writeln!(
+            self.file,
+            "{:.3},,,,,{}",
+            self.start.elapsed().as_secs_f64(),
+            csv_field(name)
+        )?;
+        self.file.flush()
+    }
+}
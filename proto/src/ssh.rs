@@ -26,9 +26,33 @@ impl client::Handler for Client {
 }
 
 pub async fn ssh_connect(farm: &str, username: String) -> anyhow::Result<Channel<Msg>> {
-    // Connect to the local SSH server
-    let config = client::Config::default();
-    let client = Client {};
+    ssh_connect_via(farm, username, None).await
+}
+
+/// Split `[user@]host[:port]`
+fn parse_jump(jump: &str, default_user: &str) -> (String, String, u16) {
+    let (user, host) = match jump.split_once('@') {
+        Some((user, host)) => (user.to_string(), host),
+        None => (
+            std::env::var("USER").unwrap_or_else(|_| default_user.to_string()),
+            jump,
+        ),
+    };
+
+    match host.rsplit_once(':').map(|(h, p)| (h, p.parse())) {
+        Some((host, Ok(port))) => (user, host.to_string(), port),
+        _ => (user, host.to_string(), 22),
+    }
+}
+
+/// Connect to the farm like ssh_connect, hopping through a jump host
+/// (`[user@]host[:port]`, like ssh's -J) if given
+pub async fn ssh_connect_via(
+    farm: &str,
+    username: String,
+    jump: Option<&str>,
+) -> anyhow::Result<Channel<Msg>> {
+    let config = Arc::new(client::Config::default());
     #[cfg(unix)]
     let agent = russh::keys::agent::client::AgentClient::connect_env().await;
     #[cfg(windows)]
@@ -37,24 +61,58 @@ pub async fn ssh_connect(farm: &str, username: String) -> anyhow::Result<Channel
     )
     .await;
 
-    let mut agent = agent.expect("Couldn't authenticate with the ssh agent");
+    let mut agent = agent.context("Couldn't authenticate with the ssh agent")?;
 
-    let mut sess = client::connect(Arc::new(config), farm, client)
+    let mut sess = match jump {
+        Some(jump) => {
+            let (jump_user, jump_host, jump_port) = parse_jump(jump, &username);
+            let mut jump_sess = client::connect(
+                Arc::clone(&config),
+                (jump_host.as_str(), jump_port),
+                Client {},
+            )
+            .await
+            .with_context(|| format!("Couldn't connect to the jump host {jump}"))?;
+            authenticate(&mut jump_sess, &jump_user, &mut agent).await?;
+
+            let (host, port) = farm.rsplit_once(':').unwrap_or((farm, "22"));
+            let tunnel = jump_sess
+                .channel_open_direct_tcpip(host, port.parse().unwrap_or(22), "127.0.0.1", 0)
+                .await
+                .with_context(|| format!("Couldn't reach {farm} through {jump}"))?;
+
+            client::connect_stream(config, tunnel.into_stream(), Client {})
+                .await
+                .with_context(|| format!("Couldn't connect to {farm} through {jump}"))?
+        }
+        None => client::connect(config, farm, Client {})
+            .await
+            .with_context(|| format!("Couldn't connect to {farm}"))?,
+    };
+    authenticate(&mut sess, &username, &mut agent).await?;
+
+    let chan = sess
+        .channel_open_session()
         .await
-        .with_context(|| format!("Couldn't connect to {farm}"))?;
+        .context("Couldn't open session")?;
 
+    Ok(chan)
+}
+
+async fn authenticate(
+    sess: &mut client::Handle<Client>,
+    username: &str,
+    agent: &mut russh::keys::agent::client::AgentClient<
+        impl tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static,
+    >,
+) -> anyhow::Result<()> {
     let keys = agent
         .request_identities()
         .await
-        .expect("Couldn't get identities from the ssh agent");
+        .context("Couldn't get identities from the ssh agent")?;
     while let Some(key) = keys.first() {
         if sess
-            .authenticate_publickey_with(
-                &username,
-                key.to_owned(),
-                Some(HashAlg::Sha256),
-                &mut agent,
-            )
+            .authenticate_publickey_with(username, key.to_owned(), Some(HashAlg::Sha256), agent)
             .await
             .is_ok()
         {
@@ -66,12 +124,7 @@ pub async fn ssh_connect(farm: &str, username: String) -> anyhow::Result<Channel
         bail!("No key was accepted by the server");
     }
 
-    let chan = sess
-        .channel_open_session()
-        .await
-        .expect("Couldn't open session");
-
-    Ok(chan)
+    Ok(())
 }
 
 pub struct Wrap(Receiver<Vec<u8>>, BytesMut);
@@ -0,0 +1,148 @@
+use anyhow::{Context, bail};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A status update sent by the server in reply to MsgStatusUpdate, e.g.
+/// `{"ts":12.345, "mainboard": {"mv": 12000, "ma": 450}}`
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct StatusUpdate {
+    /// Seconds since the server started measuring, if it says
+    pub ts: Option<f64>,
+    /// The measurements of each channel, by unit (e.g. `mv`, `ma`)
+    pub channels: BTreeMap<String, BTreeMap<String, f64>>,
+}
+
+fn number(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::Bool(b) => Some(*b as u8 as f64),
+        serde_json::Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+impl StatusUpdate {
+    /// Parse a single JSON status line
+    pub fn parse(line: &str) -> anyhow::Result<Self> {
+        let value: serde_json::Value =
+            serde_json::from_str(line.trim()).context("Status update isn't valid JSON")?;
+        let serde_json::Value::Object(obj) = value else {
+            bail!("Status update isn't a JSON object");
+        };
+
+        let mut update = Self::default();
+        for (key, value) in obj {
+            match value {
+                serde_json::Value::Object(values) => {
+                    let values = values
+                        .iter()
+                        .filter_map(|(unit, v)| number(v).map(|v| (unit.clone(), v)))
+                        .collect();
+                    update.channels.insert(key, values);
+                }
+                _ if key == "ts" => update.ts = number(&value),
+                // A channel with a single, unitless value
+                _ => {
+                    if let Some(v) = number(&value) {
+                        update
+                            .channels
+                            .insert(key, BTreeMap::from([(String::new(), v)]));
+                    }
+                }
+            }
+        }
+
+        Ok(update)
+    }
+
+    /// Parse a MsgStatusUpdate payload, which may carry several lines
+    pub fn parse_all(buf: &[u8]) -> Vec<anyhow::Result<Self>> {
+        String::from_utf8_lossy(buf)
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+
+    /// A measurement of a channel, looking the unit up case-insensitively
+    pub fn value(&self, channel: &str, unit: &str) -> Option<f64> {
+        self.channels
+            .get(channel)?
+            .iter()
+            .find(|(u, _)| u.eq_ignore_ascii_case(unit))
+            .map(|(_, v)| *v)
+    }
+}
+
+impl std::fmt::Display for StatusUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(ts) = self.ts {
+            write!(f, "[{ts:.3}] ")?;
+        }
+
+        let channels = self
+            .channels
+            .iter()
+            .map(|(name, values)| {
+                let values = values
+                    .iter()
+                    .map(|(unit, v)| format!("{v} {unit}").trim_end().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{name}: {values}")
+            })
+            .collect::<Vec<_>>();
+
+        write!(f, "{}", channels.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_and_units() {
+        let update = StatusUpdate::parse(
+            r#"{"ts": 12.345, "mainboard": {"mv": 12000, "ma": "450", "on": true, "label": "x"}, "temp": 41.5}"#,
+        )
+        .unwrap();
+
+        assert_eq!(update.ts, Some(12.345));
+        assert_eq!(update.value("mainboard", "mV"), Some(12000.0));
+        assert_eq!(update.value("mainboard", "ma"), Some(450.0));
+        assert_eq!(update.value("mainboard", "on"), Some(1.0));
+        assert_eq!(update.value("mainboard", "label"), None);
+        assert_eq!(update.value("temp", ""), Some(41.5));
+        assert_eq!(update.value("nope", "mv"), None);
+        assert_eq!(
+            update.to_string(),
+            "[12.345] mainboard: 450 ma, 12000 mv, 1 on; temp: 41.5"
+        );
+    }
+
+    #[test]
+    fn unknown_keys() {
+        // Values that aren't numbers are left out, whatever their key
+        let update =
+            StatusUpdate::parse(r#"{"name": "db845c", "list": [1, 2], "ts": "soon"}"#).unwrap();
+        assert_eq!(update, StatusUpdate::default());
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(StatusUpdate::parse(r#"{"mainboard": {"mv": 1}"#).is_err());
+        assert!(StatusUpdate::parse("[1, 2]").is_err());
+        assert!(StatusUpdate::parse("").is_err());
+    }
+
+    #[test]
+    fn several_lines() {
+        let updates = StatusUpdate::parse_all(b"{\"a\": 1}\n\n  \nnot json\n{\"ts\": 2}\n");
+
+        assert_eq!(updates.len(), 3);
+        assert_eq!(updates[0].as_ref().unwrap().value("a", ""), Some(1.0));
+        assert!(updates[1].is_err());
+        assert_eq!(updates[2].as_ref().unwrap().ts, Some(2.0));
+    }
+}